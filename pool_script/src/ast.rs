@@ -0,0 +1,643 @@
+use crate::diagnostic::{Diagnostic, Span};
+use crate::expression::Operator;
+use crate::game_data::{GameData, ItemKind};
+use crate::lexer::{Token, TokenKind};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Script {
+    pub data: Vec<DataDecl>,
+    pub functions: Vec<Function>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataDecl {
+    pub ty: Ident,
+    pub name: Ident,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: Ident,
+    pub params: Vec<Ident>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Collide {
+    pub kind: GameData,
+    pub span: Span,
+    pub args: Vec<Expr>,
+}
+
+/// The texture or the ai script name of the summon commands
+#[derive(Debug, Clone, PartialEq)]
+pub enum Name {
+    /// the name or the string written in the script
+    Literal(Ident),
+    /// `(<value>)` the str value got when summoning
+    Dynamic(Expr),
+}
+
+/// `call name(args...)` or the builtin `name(args...)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub name: Ident,
+    pub args: Vec<Expr>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    /// `let <name> = <value>` or `let <type> <name> = <value>` to declare the typed local var
+    Let {
+        ty: Option<Ident>,
+        name: Ident,
+        value: Option<Expr>,
+    },
+    Loop(Vec<Stmt>),
+    If {
+        cond: Expr,
+        then: Vec<Stmt>,
+        otherwise: Option<Vec<Stmt>>,
+    },
+    Break(Expr),
+    Wait(Expr),
+    MoveUp(Expr),
+    Kill,
+    /// `drop_item <kind> <count>` at the object
+    DropItem {
+        kind: ItemKind,
+        count: Expr,
+    },
+    Call(Call),
+    Return(Option<Expr>),
+    /// name x y z hp
+    SummonEnemy {
+        name: Name,
+        values: Vec<Expr>,
+        collide: Collide,
+        ai: Name,
+        args: Vec<Expr>,
+    },
+    /// name x y z scale angle
+    SummonBullet {
+        name: Name,
+        values: Vec<Expr>,
+        collide: Collide,
+        ai: Name,
+        args: Vec<Expr>,
+    },
+    Sin {
+        src: Expr,
+        dst: Ident,
+    },
+    Cos {
+        src: Expr,
+        dst: Ident,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f32, Span),
+    Bool(bool, Span),
+    Str(String, Span),
+    Var(Ident),
+    Neg(Box<Expr>, Span),
+    Binary(Box<Expr>, Operator, Box<Expr>),
+    Call(Call),
+    Builtin(Call),
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Number(_, span) | Expr::Bool(_, span) | Expr::Str(_, span) => *span,
+            Expr::Var(ident) => ident.span,
+            Expr::Neg(value, span) => span.to(value.span()),
+            Expr::Binary(left, _, right) => left.span().to(right.span()),
+            Expr::Call(call) | Expr::Builtin(call) => call.span,
+        }
+    }
+}
+
+const COMMANDS: &str = "end, loop, if, else, break, wait, move_up, let, kill, drop_item, call, return, summon_e, summon_b, sin, cos";
+
+pub struct AstParser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+impl<'a> AstParser<'a> {
+    pub fn new(tokens: &'a [Token], diagnostics: &'a mut Vec<Diagnostic>) -> Self {
+        Self {
+            tokens,
+            pos: 0,
+            diagnostics,
+        }
+    }
+
+    pub fn peek(&self) -> &'a Token {
+        &self.tokens[self.pos.min(self.tokens.len() - 1)]
+    }
+
+    fn next(&mut self) -> &'a Token {
+        let token = self.peek();
+        if token.kind != TokenKind::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    /// Skip the rest of the line
+    fn skip_line(&mut self) {
+        loop {
+            match self.next().kind {
+                TokenKind::NewLine | TokenKind::Eof => break,
+                _ => {}
+            }
+        }
+    }
+
+    fn expect_ident(&mut self, what: &str) -> Result<Ident, Diagnostic> {
+        let token = self.peek();
+        match &token.kind {
+            TokenKind::Ident(name) => {
+                self.next();
+                Ok(Ident {
+                    name: name.clone(),
+                    span: token.span,
+                })
+            }
+            _ => Err(unexpected(token, what))
+        }
+    }
+
+    /// Consume the token if it is the expected one
+    fn expect(&mut self, kind: TokenKind, what: &str) -> Result<(), Diagnostic> {
+        let token = self.peek();
+        if token.kind == kind {
+            self.next();
+            Ok(())
+        } else {
+            Err(unexpected(token, what))
+        }
+    }
+
+    fn expect_line_end(&mut self) -> Result<(), Diagnostic> {
+        let token = self.peek();
+        match token.kind {
+            TokenKind::NewLine => {
+                self.next();
+                Ok(())
+            }
+            TokenKind::Eof => Ok(()),
+            _ => Err(unexpected(token, "end of line"))
+        }
+    }
+
+    pub fn parse_script(&mut self) -> Script {
+        let mut script = Script::default();
+        loop {
+            let token = self.peek();
+            match &token.kind {
+                TokenKind::Eof => break,
+                TokenKind::NewLine => {
+                    self.next();
+                }
+                TokenKind::Ident(name) if name == "data" => {
+                    self.next();
+                    self.parse_data(&mut script.data);
+                }
+                TokenKind::Ident(name) if name == "function" => {
+                    self.next();
+                    let (name, params) = match self.parse_function_head() {
+                        Ok(head) => head,
+                        Err(e) => {
+                            self.diagnostics.push(e);
+                            self.skip_line();
+                            (Ident {
+                                name: String::new(),
+                                span: token.span,
+                            }, vec![])
+                        }
+                    };
+                    let body = self.parse_block(&name.name, token.span);
+                    if !name.name.is_empty() {
+                        script.functions.push(Function {
+                            name,
+                            params,
+                            body,
+                        });
+                    }
+                }
+                _ => {
+                    self.diagnostics.push(Diagnostic::warning("ignored line outside function")
+                        .with_span(token.span)
+                        .with_note("only `data` and `function` blocks are allowed here"));
+                    self.skip_line();
+                }
+            }
+        }
+        script
+    }
+
+    /// `name` or `name(params...)`
+    fn parse_function_head(&mut self) -> Result<(Ident, Vec<Ident>), Diagnostic> {
+        let name = self.expect_ident("function name")?;
+        let mut params = vec![];
+        if self.peek().kind == TokenKind::Op(Operator::LeftB) {
+            self.next();
+            if self.peek().kind != TokenKind::Op(Operator::RightB) {
+                loop {
+                    params.push(self.expect_ident("param name")?);
+                    if self.peek().kind != TokenKind::Comma {
+                        break;
+                    }
+                    self.next();
+                }
+            }
+            self.expect(TokenKind::Op(Operator::RightB), "`)`")?;
+        }
+        self.expect_line_end()?;
+        Ok((name, params))
+    }
+
+    fn parse_data(&mut self, data: &mut Vec<DataDecl>) {
+        if let Err(e) = self.expect_line_end() {
+            self.diagnostics.push(e);
+            self.skip_line();
+        }
+        loop {
+            let token = self.peek();
+            match &token.kind {
+                TokenKind::Eof => {
+                    self.diagnostics.push(Diagnostic::error("data block is not end")
+                        .with_span(token.span)
+                        .with_note("add `end` after the data"));
+                    return;
+                }
+                TokenKind::NewLine => {
+                    self.next();
+                }
+                TokenKind::Ident(name) if name == "end" => {
+                    self.next();
+                    return;
+                }
+                _ => {
+                    let result = self.expect_ident("data type")
+                        .and_then(|ty| Ok(DataDecl {
+                            ty,
+                            name: self.expect_ident("data name")?,
+                        }))
+                        .and_then(|decl| self.expect_line_end().map(|_| decl));
+                    match result {
+                        Ok(decl) => data.push(decl),
+                        Err(e) => {
+                            self.diagnostics.push(e.with_note("data is declared as `<type> <name>`"));
+                            self.skip_line();
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Parse the statements until `end`
+    fn parse_block(&mut self, name: &str, start: Span) -> Vec<Stmt> {
+        self.parse_body(name, start, false).0
+    }
+
+    /// Parse the statements until `end` or `else` if it is allowed
+    ///
+    /// Return true if the body is ended by `else`.
+    fn parse_body(&mut self, name: &str, start: Span, allow_else: bool) -> (Vec<Stmt>, bool) {
+        let mut body = vec![];
+        loop {
+            let token = self.peek();
+            match &token.kind {
+                TokenKind::Eof => {
+                    self.diagnostics.push(Diagnostic::error(format!("`{}` is not end", name))
+                        .with_span(start)
+                        .with_note("add `end` after it"));
+                    return (body, false);
+                }
+                TokenKind::NewLine => {
+                    self.next();
+                }
+                TokenKind::Ident(name) if name == "end" => {
+                    self.next();
+                    if let Err(e) = self.expect_line_end() {
+                        self.diagnostics.push(e);
+                        self.skip_line();
+                    }
+                    return (body, false);
+                }
+                TokenKind::Ident(name) if name == "else" => {
+                    self.next();
+                    if !allow_else {
+                        self.diagnostics.push(Diagnostic::error("`else` without `if`")
+                            .with_span(token.span));
+                        self.skip_line();
+                        continue;
+                    }
+                    if let Err(e) = self.expect_line_end() {
+                        self.diagnostics.push(e);
+                        self.skip_line();
+                    }
+                    return (body, true);
+                }
+                _ => match self.parse_stmt() {
+                    Ok(stmt) => body.push(stmt),
+                    Err(e) => {
+                        self.diagnostics.push(e);
+                        self.skip_line();
+                    }
+                }
+            }
+        }
+    }
+
+    fn parse_stmt(&mut self) -> Result<Stmt, Diagnostic> {
+        let token = self.peek();
+        let command = self.expect_ident("command")?;
+        let stmt = match command.name.as_str() {
+            "let" => {
+                let mut ty = None;
+                let mut name = self.expect_ident("var name")?;
+                if matches!(self.peek().kind, TokenKind::Ident(_)) {
+                    ty = Some(name);
+                    name = self.expect_ident("var name")?;
+                }
+                let value = if self.peek().kind == TokenKind::Assign {
+                    self.next();
+                    Some(self.parse_expr()?)
+                } else {
+                    None
+                };
+                Stmt::Let {
+                    ty,
+                    name,
+                    value,
+                }
+            }
+            "loop" => {
+                self.expect_line_end()?;
+                return Ok(Stmt::Loop(self.parse_block("loop", token.span)));
+            }
+            "if" => {
+                let cond = self.parse_expr()?;
+                self.expect_line_end()?;
+                let (then, has_else) = self.parse_body("if", token.span, true);
+                let otherwise = if has_else {
+                    Some(self.parse_block("else", token.span))
+                } else {
+                    None
+                };
+                return Ok(Stmt::If { cond, then, otherwise });
+            }
+            "break" => Stmt::Break(self.parse_expr()?),
+            "wait" => Stmt::Wait(self.parse_expr()?),
+            "move_up" => Stmt::MoveUp(self.parse_expr()?),
+            "kill" => Stmt::Kill,
+            "drop_item" => {
+                let kind_name = self.expect_ident("item kind")?;
+                let kind = ItemKind::try_from(kind_name.name.as_str())
+                    .map_err(|_| Diagnostic::error(format!("unknown item `{}`", kind_name.name))
+                        .with_span(kind_name.span)
+                        .with_note("expected power, point, bomb, life or full_power"))?;
+                Stmt::DropItem { kind, count: self.parse_expr()? }
+            }
+            "call" => Stmt::Call(self.parse_call(token.span)?),
+            "return" => if matches!(self.peek().kind, TokenKind::NewLine | TokenKind::Eof) {
+                Stmt::Return(None)
+            } else {
+                Stmt::Return(Some(self.parse_expr()?))
+            },
+            "summon_e" | "summon_b" => {
+                let name = self.parse_name("texture name")?;
+                let count = if command.name == "summon_e" { 4 } else { 5 };
+                let mut values = Vec::with_capacity(count);
+                for _ in 0..count {
+                    values.push(self.parse_unary()?);
+                }
+                let collide_name = self.expect_ident("collide type")?;
+                let kind = GameData::try_from(collide_name.name.as_str()).ok()
+                    .filter(|x| *x == GameData::CircleCollide)
+                    .ok_or_else(|| Diagnostic::error(format!("unknown collide type `{}`", collide_name.name))
+                        .with_span(collide_name.span)
+                        .with_note("expected circle"))?;
+                let mut collide_args = vec![];
+                for _ in 0..kind.get_args_count() {
+                    collide_args.push(self.parse_unary()?);
+                }
+                let collide = Collide {
+                    kind,
+                    span: collide_name.span,
+                    args: collide_args,
+                };
+                let ai = self.parse_name("ai script name")?;
+                let mut args = vec![];
+                while !matches!(self.peek().kind, TokenKind::NewLine | TokenKind::Eof) {
+                    args.push(self.parse_unary()?);
+                }
+                if command.name == "summon_e" {
+                    Stmt::SummonEnemy { name, values, collide, ai, args }
+                } else {
+                    Stmt::SummonBullet { name, values, collide, ai, args }
+                }
+            }
+            "sin" | "cos" => {
+                let src = self.parse_expr()?;
+                self.expect(TokenKind::Comma, "`,`")
+                    .map_err(|e| e.with_note(format!("expected `{} <src>, <dst>`", command.name)))?;
+                let dst = self.expect_ident("var name")?;
+                if command.name == "sin" {
+                    Stmt::Sin { src, dst }
+                } else {
+                    Stmt::Cos { src, dst }
+                }
+            }
+            _ => {
+                return Err(Diagnostic::error(format!("unknown command `{}`", command.name))
+                    .with_span(command.span)
+                    .with_note(format!("expected one of {}", COMMANDS)));
+            }
+        };
+        self.expect_line_end()?;
+        Ok(stmt)
+    }
+
+    pub fn parse_expr(&mut self) -> Result<Expr, Diagnostic> {
+        self.parse_binary(1)
+    }
+
+    /// Parse the left associative binary expression whose operator priority is at least `priority`
+    fn parse_binary(&mut self, priority: u8) -> Result<Expr, Diagnostic> {
+        let mut left = if priority >= 3 {
+            self.parse_unary()?
+        } else {
+            self.parse_binary(priority + 1)?
+        };
+        while let TokenKind::Op(op) = self.peek().kind {
+            if op.get_priority() != priority {
+                break;
+            }
+            self.next();
+            let right = if priority >= 3 {
+                self.parse_unary()?
+            } else {
+                self.parse_binary(priority + 1)?
+            };
+            left = Expr::Binary(Box::new(left), op, Box::new(right));
+        }
+        Ok(left)
+    }
+
+    /// Parse the call after `call`
+    fn parse_call(&mut self, start: Span) -> Result<Call, Diagnostic> {
+        let name = self.expect_ident("function name")?;
+        self.expect(TokenKind::Op(Operator::LeftB), "`(`")
+            .map_err(|e| e.with_note("expected `call <name>(<args>...)`"))?;
+        self.parse_args(name, start)
+    }
+
+    /// Parse the args after `(`
+    fn parse_args(&mut self, name: Ident, start: Span) -> Result<Call, Diagnostic> {
+        let mut args = vec![];
+        if self.peek().kind != TokenKind::Op(Operator::RightB) {
+            loop {
+                args.push(self.parse_expr()?);
+                if self.peek().kind != TokenKind::Comma {
+                    break;
+                }
+                self.next();
+            }
+        }
+        let end = self.peek().span;
+        self.expect(TokenKind::Op(Operator::RightB), "`)`")?;
+        Ok(Call {
+            name,
+            args,
+            span: start.to(end),
+        })
+    }
+
+    fn parse_name(&mut self, what: &str) -> Result<Name, Diagnostic> {
+        let token = self.peek();
+        match &token.kind {
+            TokenKind::Op(Operator::LeftB) => Ok(Name::Dynamic(self.parse_unary()?)),
+            TokenKind::Str(name) => {
+                self.next();
+                Ok(Name::Literal(Ident {
+                    name: name.clone(),
+                    span: token.span,
+                }))
+            }
+            _ => Ok(Name::Literal(self.expect_ident(what)?))
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, Diagnostic> {
+        let token = self.peek();
+        let expr = match &token.kind {
+            TokenKind::Op(Operator::SUB) => {
+                self.next();
+                return Ok(Expr::Neg(Box::new(self.parse_unary()?), token.span));
+            }
+            TokenKind::Op(Operator::LeftB) => {
+                self.next();
+                let expr = self.parse_expr()?;
+                self.expect(TokenKind::Op(Operator::RightB), "`)`")
+                    .map_err(|e| e.with_note(format!("to close the `(` at column {}", token.span.column + 1)))?;
+                return Ok(expr);
+            }
+            TokenKind::Number(value) => Expr::Number(*value, token.span),
+            TokenKind::Str(value) => Expr::Str(value.clone(), token.span),
+            TokenKind::Ident(name) if name == "true" || name == "false" => Expr::Bool(name == "true", token.span),
+            TokenKind::Ident(name) if name == "call" => {
+                self.next();
+                return Ok(Expr::Call(self.parse_call(token.span)?));
+            }
+            //the builtin function is the name followed by `(` without spaces
+            TokenKind::Ident(name) if matches!(self.tokens.get(self.pos + 1), Some(Token { kind: TokenKind::Op(Operator::LeftB), span })
+                if span.line == token.span.line && span.column == token.span.column + token.span.len) => {
+                self.next();
+                self.next();
+                let name = Ident {
+                    name: name.clone(),
+                    span: token.span,
+                };
+                return Ok(Expr::Builtin(self.parse_args(name, token.span)?));
+            }
+            TokenKind::Ident(name) => Expr::Var(Ident {
+                name: name.clone(),
+                span: token.span,
+            }),
+            _ => return Err(unexpected(token, "expression"))
+        };
+        self.next();
+        Ok(expr)
+    }
+}
+
+fn unexpected(token: &Token, expected: &str) -> Diagnostic {
+    let found = match &token.kind {
+        TokenKind::Ident(name) => format!("`{}`", name),
+        TokenKind::Number(value) => format!("`{}`", value),
+        TokenKind::Str(value) => format!("`\"{}\"`", value),
+        TokenKind::Op(op) => format!("`{}`", op),
+        TokenKind::Comma => "`,`".into(),
+        TokenKind::Assign => "`=`".into(),
+        TokenKind::NewLine => "end of line".into(),
+        TokenKind::Eof => "end of file".into(),
+    };
+    Diagnostic::error(format!("expected {} but found {}", expected, found))
+        .with_span(token.span)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast::{AstParser, Expr, Name, Stmt};
+    use crate::expression::Operator;
+    use crate::lexer::tokenize;
+
+    #[test]
+    fn parse_stmt() {
+        let mut diagnostics = vec![];
+        let tokens = tokenize("function tick\n  let a = 1 - -b * (c + 2) < 3\n  summon_b  (x) 1 -2 a 0.5 -a circle 1 ai -1\nend\n", &mut diagnostics);
+        let script = AstParser::new(&tokens, &mut diagnostics).parse_script();
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let body = &script.functions[0].body;
+        match &body[0] {
+            Stmt::Let { value: Some(Expr::Binary(left, Operator::LT, _)), .. } => {
+                match left.as_ref() {
+                    Expr::Binary(one, Operator::SUB, mul) => {
+                        assert!(matches!(one.as_ref(), Expr::Number(v, _) if *v == 1.0));
+                        assert!(matches!(mul.as_ref(), Expr::Binary(neg, Operator::MUL, _) if matches!(neg.as_ref(), Expr::Neg(..))));
+                    }
+                    x => panic!("unexpected {:?}", x)
+                }
+            }
+            x => panic!("unexpected {:?}", x)
+        }
+        match &body[1] {
+            Stmt::SummonBullet { name, values, collide, ai, args } => {
+                assert!(matches!(name, Name::Dynamic(Expr::Var(x)) if x.name == "x"));
+                assert_eq!(values.len(), 5);
+                assert!(matches!(&values[1], Expr::Neg(..)));
+                assert_eq!(collide.args.len(), 1);
+                assert!(matches!(ai, Name::Literal(x) if x.name == "ai"));
+                assert_eq!(args.len(), 1);
+            }
+            x => panic!("unexpected {:?}", x)
+        }
+    }
+}
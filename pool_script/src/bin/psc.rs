@@ -1,41 +1,99 @@
 use std::ffi::OsString;
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
-use std::path::Path;
+use std::io::{BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
 
 use log::LevelFilter;
 
+use pool_script::harness::RunConfig;
 use pool_script::pool_script::Parser;
 
-fn compile(name: OsString, file: File, output_dir: &String) {
+fn compile(name: OsString, mut file: File, output_dir: &String) {
     println!("compiling file {:?}", name);
 
-    let script = Parser::new(file).try_parse();
-    if script.is_err() {
-        eprintln!("parse script failed: {}", script.err().expect("parse failed: Unknown"));
+    let mut source = String::new();
+    if let Err(e) = file.read_to_string(&mut source) {
+        eprintln!("read file failed: {}", e);
         return;
     }
-    let output_name = OsString::from(format!("{}/{}b", output_dir, name.to_str().unwrap()));
-    let output_file = File::create(&output_name);
-    if output_file.is_err() {
-        eprintln!("open output file failed: {}", script.err().expect("save failed: Unknown"));
+    let file_name = name.to_string_lossy();
+    let mut parser = Parser::new(source.as_bytes());
+    let script = parser.try_parse();
+    let diagnostics = match &script {
+        Ok(_) => parser.diagnostics(),
+        Err(diagnostics) => diagnostics.as_slice()
+    };
+    for diagnostic in diagnostics {
+        eprintln!("{}", diagnostic.render(&file_name, &source));
+    }
+    if let Err(diagnostics) = &script {
+        eprintln!("parse script failed with {} error(s)", diagnostics.iter().filter(|x| x.is_error()).count());
         return;
     }
+    let output_name = OsString::from(format!("{}/{}b", output_dir, name.to_str().unwrap()));
+    let output_file = match File::create(&output_name) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("open output file failed: {}", e);
+            return;
+        }
+    };
 
-    let result = script.unwrap().save(&mut BufWriter::new(output_file.unwrap()));
-    if result.is_err() {
-        eprintln!("save output file failed: {}", result.err().expect("save failed: Unknown"));
+    if let Err(e) = script.unwrap().save(&mut BufWriter::new(output_file)) {
+        eprintln!("save output file failed: {}", e);
     } else {
         println!("compiled file {:?} into {:?}", name, output_name);
     }
 }
 
-fn decompile(name: OsString, file: File) {
+fn decompile(name: OsString, file: File, output_dir: &String) {
     println!("decompiling file {:?}", name);
 
+    let script = match pool_script::PoolScriptBin::try_parse_bin(BufReader::new(file)) {
+        Ok(script) => script,
+        Err(e) => {
+            eprintln!("parse script failed: {}", e);
+            return;
+        }
+    };
+    let source = match pool_script::decompile::decompile(&script) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("decompile script failed: {}", e);
+            return;
+        }
+    };
+    let name_str = name.to_str().unwrap();
+    let output_name = OsString::from(format!("{}/{}.pthps", output_dir, name_str.strip_suffix(".pthpsb").unwrap_or(name_str)));
+    if let Err(e) = std::fs::write(&output_name, source) {
+        eprintln!("save output file failed: {}", e);
+    } else {
+        println!("decompiled file {:?} into {:?}", name, output_name);
+    }
+}
+
+fn disassemble(name: &str, file: File) {
     let script = pool_script::PoolScriptBin::try_parse_bin(BufReader::new(file));
-    if script.is_err() {
-        eprintln!("parse script failed: {}", script.err().expect("parse failed: Unknown"));
+    match script.and_then(|x| pool_script::disasm::disassemble(&x)) {
+        Ok(text) => println!("{}:\n{}", name, text),
+        Err(e) => eprintln!("disassemble script failed: {}", e),
+    }
+}
+
+/// Run the script of one object and print the commands it submits
+fn run(dir: &Path, name: &str, settings: &[String]) {
+    let result = RunConfig::parse(settings.iter().map(String::as_str))
+        .and_then(|config| pool_script::harness::run(dir, name, &config));
+    match result {
+        Ok(lines) => {
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+        Err(e) => {
+            eprintln!("run script failed: {}", e);
+            std::process::exit(1);
+        }
     }
 }
 
@@ -48,10 +106,13 @@ fn main() {
     let args: Vec<String> = std::env::args().collect();
 
     let mut output_dir = ".".to_string();
+    let mut script_dir = PathBuf::from("script");
     let args = args.into_iter().filter(|arg| {
         if arg.starts_with("--") {
-            if arg.starts_with("--output=") {
-                output_dir = String::from(&arg["--output=".len()..]);
+            if let Some(dir) = arg.strip_prefix("--output=") {
+                output_dir = String::from(dir);
+            } else if let Some(path) = arg.strip_prefix("--dir=") {
+                script_dir = PathBuf::from(path);
             }
             false
         } else {
@@ -60,13 +121,16 @@ fn main() {
     }).collect::<Vec<String>>();
 
     if args.len() == 1 {
-        println!("psc compile <dir...>")
+        println!("psc compile <dir...>");
+        println!("psc decompile <dir...>");
+        println!("psc disasm <file...>");
+        println!("psc run <script> [ticks=60] [args=1,2] [player=0,-100] [seed=0] [budget=100000] [--dir=script]");
     } else if args.len() > 2 && args[1] == "compile" {
         let run_dir = std::env::current_dir().unwrap();
         for path in args.iter().skip(2) {
             let src_path = run_dir.join(Path::new(path));
             if src_path.is_dir() {
-                let dir = src_path.read_dir().expect(&*("We need a directory, not ".to_owned() + src_path.to_str().unwrap()));
+                let dir = src_path.read_dir().unwrap_or_else(|_| panic!("We need a directory, not {}", src_path.to_str().unwrap()));
                 println!("compiling dir {:?}", dir);
                 for file in dir {
                     match file {
@@ -99,15 +163,15 @@ fn main() {
         for path in args.iter().skip(2) {
             let path = run_dir.join(Path::new(path));
             if path.is_dir() {
-                let dir = path.read_dir().expect(&*("We need a directory, not ".to_owned() + path.to_str().unwrap()));
+                let dir = path.read_dir().unwrap_or_else(|_| panic!("We need a directory, not {}", path.to_str().unwrap()));
                 println!("decompiling dir {:?}", dir);
                 for file in dir {
                     match file {
                         Ok(entry) => {
                             if let Ok(file_type) = entry.file_type() {
-                                if file_type.is_file() && entry.file_name().to_str().to_owned().unwrap().ends_with(".pthps") {
+                                if file_type.is_file() && entry.file_name().to_str().to_owned().unwrap().ends_with(".pthpsb") {
                                     match File::open(entry.path()) {
-                                        Ok(file) => decompile(entry.file_name(), file),
+                                        Ok(file) => decompile(entry.file_name(), file, &output_dir),
                                         Err(err) => eprintln!("open file failed: {}", err)
                                     }
                                 }
@@ -120,10 +184,19 @@ fn main() {
                 }
             } else {
                 match File::open(&path) {
-                    Ok(file) => decompile(path.file_name().unwrap().to_os_string(), file),
+                    Ok(file) => decompile(path.file_name().unwrap().to_os_string(), file, &output_dir),
                     Err(err) => eprintln!("open file failed: {}", err)
                 }
             }
         }
+    } else if args.len() > 2 && args[1] == "disasm" {
+        for path in args.iter().skip(2) {
+            match File::open(path) {
+                Ok(file) => disassemble(path, file),
+                Err(err) => eprintln!("open file failed: {}", err)
+            }
+        }
+    } else if args.len() > 2 && args[1] == "run" {
+        run(&script_dir, &args[2], &args[3..]);
     }
 }
@@ -0,0 +1,150 @@
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind};
+
+use crate::data_type::DataType;
+
+/// The math functions could be used in the expression
+///
+/// The args are popped from the calc stack and the result is pushed back.
+/// The angles are in degrees, the right angle is zero and up is 90.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum Builtin {
+    Sin = 41,
+    Cos = 42,
+    Atan2 = 43,
+    Sqrt = 44,
+    Abs = 45,
+    Min = 46,
+    Max = 47,
+    Floor = 48,
+    Pow = 49,
+    Lerp = 50,
+    Clamp = 51,
+    /// the angle from self to the player
+    PlayerAngle = 52,
+    /// the random value in [0, 1)
+    Rand = 53,
+    /// the random value in [a, b)
+    RandRange = 54,
+    /// the random integer in [0, n)
+    RandInt = 55,
+    /// the integer part of the value
+    Int = 56,
+}
+
+impl Builtin {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Sin => "sin",
+            Builtin::Cos => "cos",
+            Builtin::Atan2 => "atan2",
+            Builtin::Sqrt => "sqrt",
+            Builtin::Abs => "abs",
+            Builtin::Min => "min",
+            Builtin::Max => "max",
+            Builtin::Floor => "floor",
+            Builtin::Pow => "pow",
+            Builtin::Lerp => "lerp",
+            Builtin::Clamp => "clamp",
+            Builtin::PlayerAngle => "player_angle",
+            Builtin::Rand => "rand",
+            Builtin::RandRange => "rand_range",
+            Builtin::RandInt => "rand_int",
+            Builtin::Int => "int",
+        }
+    }
+
+    pub fn get_args_count(&self) -> usize {
+        match self {
+            Builtin::PlayerAngle | Builtin::Rand => 0,
+            Builtin::Sin | Builtin::Cos | Builtin::Sqrt | Builtin::Abs | Builtin::Floor | Builtin::RandInt | Builtin::Int => 1,
+            Builtin::Atan2 | Builtin::Min | Builtin::Max | Builtin::Pow | Builtin::RandRange => 2,
+            Builtin::Lerp | Builtin::Clamp => 3,
+        }
+    }
+
+    /// The type of the result
+    pub fn ty(&self) -> DataType {
+        match self {
+            Builtin::RandInt | Builtin::Int => DataType::I32,
+            _ => DataType::F32,
+        }
+    }
+
+    /// The function uses the game random so it cannot run in parallel
+    pub fn is_random(&self) -> bool {
+        matches!(self, Builtin::Rand | Builtin::RandRange | Builtin::RandInt)
+    }
+
+    /// Calculate the const value, None if it depends on the game
+    pub fn operate(&self, args: &[f32]) -> Option<f32> {
+        Some(match self {
+            Builtin::Sin => args[0].to_radians().sin(),
+            Builtin::Cos => args[0].to_radians().cos(),
+            Builtin::Atan2 => args[0].atan2(args[1]).to_degrees(),
+            Builtin::Sqrt => args[0].sqrt(),
+            Builtin::Abs => args[0].abs(),
+            Builtin::Min => args[0].min(args[1]),
+            Builtin::Max => args[0].max(args[1]),
+            Builtin::Floor => args[0].floor(),
+            Builtin::Pow => args[0].powf(args[1]),
+            Builtin::Lerp => args[0] + (args[1] - args[0]) * args[2],
+            Builtin::Clamp => args[0].max(args[1]).min(args[2]),
+            Builtin::Int => args[0].trunc(),
+            Builtin::PlayerAngle | Builtin::Rand | Builtin::RandRange | Builtin::RandInt => return None,
+        })
+    }
+}
+
+impl TryFrom<u8> for Builtin {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            41 => Ok(Builtin::Sin),
+            42 => Ok(Builtin::Cos),
+            43 => Ok(Builtin::Atan2),
+            44 => Ok(Builtin::Sqrt),
+            45 => Ok(Builtin::Abs),
+            46 => Ok(Builtin::Min),
+            47 => Ok(Builtin::Max),
+            48 => Ok(Builtin::Floor),
+            49 => Ok(Builtin::Pow),
+            50 => Ok(Builtin::Lerp),
+            51 => Ok(Builtin::Clamp),
+            52 => Ok(Builtin::PlayerAngle),
+            53 => Ok(Builtin::Rand),
+            54 => Ok(Builtin::RandRange),
+            55 => Ok(Builtin::RandInt),
+            56 => Ok(Builtin::Int),
+            _ => Err(Error::new(ErrorKind::InvalidData, format!("[parse builtin]no such builtin command {}", value)))
+        }
+    }
+}
+
+impl TryFrom<&str> for Builtin {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "sin" => Ok(Builtin::Sin),
+            "cos" => Ok(Builtin::Cos),
+            "atan2" => Ok(Builtin::Atan2),
+            "sqrt" => Ok(Builtin::Sqrt),
+            "abs" => Ok(Builtin::Abs),
+            "min" => Ok(Builtin::Min),
+            "max" => Ok(Builtin::Max),
+            "floor" => Ok(Builtin::Floor),
+            "pow" => Ok(Builtin::Pow),
+            "lerp" => Ok(Builtin::Lerp),
+            "clamp" => Ok(Builtin::Clamp),
+            "player_angle" => Ok(Builtin::PlayerAngle),
+            "rand" => Ok(Builtin::Rand),
+            "rand_range" => Ok(Builtin::RandRange),
+            "rand_int" => Ok(Builtin::RandInt),
+            "int" => Ok(Builtin::Int),
+            _ => Err(Error::new(ErrorKind::InvalidData, "[parse builtin]expected builtin function but found : ".to_owned() + value))
+        }
+    }
+}
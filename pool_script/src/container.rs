@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::io::{BufReader, Error, ErrorKind, Read};
+
+use crate::data_type::DataType;
+use crate::pool_script::{Compile, parse_functions, PoolScriptBin, read_str, write_functions};
+
+/// The first bytes of the v1 container, the version 0 files start with the version 0
+pub const MAGIC: &[u8; 4] = b"PTPS";
+pub const VERSION: u32 = 1;
+
+/// The names and the types of the data slots ordered by the index
+pub const SECTION_DATA: u8 = 1;
+/// The functions with the headers
+pub const SECTION_FUNCTIONS: u8 = 2;
+/// The strings used by the str values
+pub const SECTION_STRINGS: u8 = 3;
+
+/// magic, version and the section count
+const HEADER_LEN: usize = 9;
+/// kind, offset and length
+const SECTION_ENTRY_LEN: usize = 9;
+
+/// Write the container, the CRC of all the bytes before it is at the end
+pub fn write(bin: &PoolScriptBin) -> Result<Vec<u8>, Error> {
+    let mut data = bin.data.iter().collect::<Vec<_>>();
+    data.sort_by_key(|(_, idx)| **idx);
+    let mut data_section = vec![data.len() as u8];
+    for (name, idx) in data {
+        name.as_str().flush(&mut data_section)?;
+        data_section.push(bin.data_types.get(*idx as usize).copied().unwrap_or_default() as u8);
+    }
+    let mut function_section = vec![];
+    write_functions(&bin.functions, &mut function_section)?;
+    let mut string_section = (bin.strings.len() as u16).to_be_bytes().to_vec();
+    for x in &bin.strings {
+        x.as_str().flush(&mut string_section)?;
+    }
+
+    let sections = [(SECTION_DATA, data_section), (SECTION_FUNCTIONS, function_section), (SECTION_STRINGS, string_section)];
+    let mut binary = MAGIC.to_vec();
+    binary.extend_from_slice(&VERSION.to_be_bytes());
+    binary.push(sections.len() as u8);
+    let mut offset = HEADER_LEN + sections.len() * SECTION_ENTRY_LEN;
+    for (kind, section) in &sections {
+        binary.push(*kind);
+        binary.extend_from_slice(&(offset as u32).to_be_bytes());
+        binary.extend_from_slice(&(section.len() as u32).to_be_bytes());
+        offset += section.len();
+    }
+    for (_, section) in &sections {
+        binary.extend_from_slice(section);
+    }
+    let crc = crc32(&binary);
+    binary.extend_from_slice(&crc.to_be_bytes());
+    Ok(binary)
+}
+
+/// Read the container without verifying the functions, the unknown sections are skipped
+pub fn read(bytes: &[u8]) -> Result<PoolScriptBin, Error> {
+    if bytes.len() < HEADER_LEN + 4 {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "[container]the file is too short"));
+    }
+    let (body, crc) = bytes.split_at(bytes.len() - 4);
+    if crc32(body) != u32::from_be_bytes(crc.try_into().unwrap()) {
+        return Err(Error::new(ErrorKind::InvalidData, "[container]the checksum does not match"));
+    }
+    let version = u32::from_be_bytes(body[4..8].try_into().unwrap());
+    if version != VERSION {
+        return Err(Error::new(ErrorKind::InvalidData, format!("[container]unsupported version {}", version)));
+    }
+    let mut data = HashMap::new();
+    let mut data_types = vec![];
+    let mut strings = vec![];
+    let mut functions = HashMap::new();
+    for idx in 0..body[8] as usize {
+        let start = HEADER_LEN + idx * SECTION_ENTRY_LEN;
+        let entry = body.get(start..start + SECTION_ENTRY_LEN)
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "[container]the section table is not end"))?;
+        let offset = u32::from_be_bytes(entry[1..5].try_into().unwrap()) as usize;
+        let len = u32::from_be_bytes(entry[5..9].try_into().unwrap()) as usize;
+        let section = offset.checked_add(len).and_then(|end| body.get(offset..end))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("[container]section {} is out of the file", entry[0])))?;
+        match entry[0] {
+            SECTION_DATA => {
+                let (names, types) = read_data(section)?;
+                data = names;
+                data_types = types;
+            }
+            SECTION_FUNCTIONS => functions = parse_functions(&mut BufReader::new(section))?,
+            SECTION_STRINGS => strings = read_strings(section)?,
+            kind => log::warn!("skip the unknown section {}", kind)
+        }
+    }
+    Ok(PoolScriptBin {
+        version,
+        data,
+        data_types,
+        strings,
+        functions,
+    })
+}
+
+fn read_data(section: &[u8]) -> Result<(HashMap<String, u8>, Vec<DataType>), Error> {
+    let mut reader = BufReader::new(section);
+    let mut count = [0];
+    reader.read_exact(&mut count)?;
+    let mut data = HashMap::new();
+    let mut types = vec![];
+    for idx in 0..count[0] {
+        let name = read_str(&mut reader, &mut vec![], false)?;
+        if data.insert(name, idx).is_some() {
+            return Err(Error::new(ErrorKind::InvalidData, "[container]the data is declared twice"));
+        }
+        let mut ty = [0];
+        reader.read_exact(&mut ty)?;
+        types.push(DataType::try_from(ty[0])?);
+    }
+    Ok((data, types))
+}
+
+fn read_strings(section: &[u8]) -> Result<Vec<String>, Error> {
+    let mut reader = BufReader::new(section);
+    let mut count = [0; 2];
+    reader.read_exact(&mut count)?;
+    (0..u16::from_be_bytes(count)).map(|_| read_str(&mut reader, &mut vec![], false)).collect()
+}
+
+/// The CRC-32 used by zip and png
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::BufReader;
+
+    use crate::container::{crc32, MAGIC, write};
+    use crate::pool_script::{Parser, PoolScriptBin};
+
+    #[test]
+    fn container_round_trip() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        let source = "data
+f32 speed
+vec2 target
+str texture
+end
+
+function tick
+    let texture = \"bullet\"
+    move_up speed + target.x
+end
+";
+        let bin = Parser::new(source.as_bytes()).try_parse().unwrap();
+        let binary = write(&bin).unwrap();
+        assert!(binary.starts_with(MAGIC));
+        let loaded = PoolScriptBin::try_parse_bin(BufReader::new(&binary[..])).unwrap();
+        assert_eq!(loaded.version, 1);
+        assert_eq!(loaded.data, bin.data);
+        assert_eq!(loaded.data_types, bin.data_types);
+        assert_eq!(loaded.strings, vec!["bullet".to_string()]);
+        assert_eq!(loaded.functions["tick"].code, bin.functions["tick"].code);
+
+        let mut broken = binary.clone();
+        broken[20] ^= 1;
+        let e = PoolScriptBin::try_parse_bin(BufReader::new(&broken[..])).unwrap_err();
+        assert_eq!(e.to_string(), "[container]the checksum does not match");
+        let e = PoolScriptBin::try_parse_bin(BufReader::new(&binary[..binary.len() - 1])).unwrap_err();
+        assert_eq!(e.to_string(), "[container]the checksum does not match");
+    }
+}
@@ -1,15 +1,37 @@
+use std::cell::RefCell;
 use std::collections::{HashMap, LinkedList};
 use std::convert::TryFrom;
-use std::io::{Error, ErrorKind};
-use std::str::FromStr;
 
-use crate::expression::ExpressionElement;
+use crate::data_type::DataType;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::expression::{Expression, ExpressionElement};
 use crate::game_data::GameData;
+use crate::pool_script::Loop;
+
+/// What the loader needs to know about the compiled function
+#[derive(Debug, Default)]
+pub struct FunctionInfo {
+    /// the max count of the local vars at the same time
+    pub max_stack: u8,
+    /// the max count of the values in the calc stack
+    pub max_calc: u8,
+    pub loops: Vec<Loop>,
+    /// the game random is used without calling other functions
+    pub random: bool,
+    pub calls: Vec<String>,
+}
 
 pub struct Context<'a> {
     heap: &'a HashMap<String, u8>,
-    stack: LinkedList<Vec<String>>,
+    /// the types of the data slots, all f32 if none
+    data_types: Option<&'a [DataType]>,
+    /// the param count of the functions could be called
+    functions: Option<&'a HashMap<String, usize>>,
+    /// the interned strings of the script
+    strings: Option<&'a RefCell<Vec<String>>>,
+    stack: LinkedList<Vec<(String, DataType)>>,
     stack_count: u8,
+    info: FunctionInfo,
 }
 
 impl<'a> Context<'a> {
@@ -18,36 +40,77 @@ impl<'a> Context<'a> {
         list.push_back(Vec::new());
         Self {
             heap,
+            data_types: None,
+            functions: None,
+            strings: None,
             stack: list,
             stack_count: 0,
+            info: FunctionInfo::default(),
         }
     }
 
-    pub fn parse_value(&self, string: &str) -> Result<ExpressionElement, Error> {
-        if let Ok(value) = f32::from_str(string) {
-            return Ok(ExpressionElement::CONST(value));
-        }
-        self.find_index(string)
+    pub fn with_functions(mut self, functions: &'a HashMap<String, usize>) -> Self {
+        self.functions = Some(functions);
+        self
+    }
+
+    pub fn with_data_types(mut self, data_types: &'a [DataType]) -> Self {
+        self.data_types = Some(data_types);
+        self
+    }
+
+    pub fn with_strings(mut self, strings: &'a RefCell<Vec<String>>) -> Self {
+        self.strings = Some(strings);
+        self
     }
 
-    pub fn find_index(&self, name: &str) -> Result<ExpressionElement, Error> {
-        if let Ok(value) = GameData::try_from(name) {
-            return Ok(ExpressionElement::GAME(value as u8));
+    /// Get the index of the string in the script, none if the strings are not allowed
+    pub fn intern(&self, value: &str) -> Option<u32> {
+        let mut strings = self.strings?.borrow_mut();
+        let idx = match strings.iter().position(|x| x == value) {
+            Some(idx) => idx,
+            None => {
+                strings.push(value.to_string());
+                strings.len() - 1
+            }
+        };
+        Some(idx as u32)
+    }
+
+    /// Get the param count of the function
+    pub fn find_function(&self, name: &str) -> Option<usize> {
+        self.functions.and_then(|x| x.get(name)).copied()
+    }
+
+    /// The span of the error is relative to `name`
+    pub fn find_index(&self, name: &str) -> Result<ExpressionElement, Diagnostic> {
+        self.find_var(name).map(|(index, _)| index)
+    }
+
+    /// Get the index and the type of the var, the field of vec2 is f32
+    pub fn find_var(&self, name: &str) -> Result<(ExpressionElement, DataType), Diagnostic> {
+        if let Some(value) = GameData::try_from(name).ok().filter(GameData::is_var) {
+            return Ok((ExpressionElement::GAME(value as u8), value.ty()));
         } else if let Some(value) = self.heap.get(name) {
-            return Ok(ExpressionElement::DATA(*value));
+            let ty = self.data_types.and_then(|x| x.get(*value as usize)).copied()
+                .filter(|x| *x != DataType::Vec2)
+                .unwrap_or(DataType::F32);
+            return Ok((ExpressionElement::DATA(*value), ty));
         } else if self.stack_count > 0 {
             let mut count = self.stack_count;
             for ss in self.stack.iter().rev() {
-                for s in ss.iter().rev() {
+                for (s, ty) in ss.iter().rev() {
                     if s == name {
-                        return Ok(ExpressionElement::STACK(count - 1));
+                        return Ok((ExpressionElement::STACK(count - 1), *ty));
                     }
                     count -= 1;
                 }
             }
         }
 
-        return Err(Error::new(ErrorKind::InvalidData, "[find index]Unknown var name: ".to_owned() + name));
+        Err(Diagnostic::error(format!("unknown var name `{}`", name))
+            .with_span(Span::new(0, name.len()))
+            .with_note("declare it in the data block or by `let` before using"))
     }
 
     pub fn push_stack(&mut self) {
@@ -60,8 +123,35 @@ impl<'a> Context<'a> {
     }
 
     pub fn push_name(&mut self, name: &str) {
+        self.push_var(name, DataType::F32);
+    }
+
+    pub fn push_var(&mut self, name: &str, ty: DataType) {
         let vec = self.stack.back_mut().unwrap();
-        vec.push(name.to_string());
+        vec.push((name.to_string(), ty));
         self.stack_count += 1;
+        self.info.max_stack = self.info.max_stack.max(self.stack_count);
+    }
+
+    /// Record the calc stack and the calls used by the expression
+    pub fn use_expression(&mut self, exp: &Expression) {
+        self.info.max_calc = self.info.max_calc.max(exp.calc_depth().min(u8::MAX as usize) as u8);
+        for x in exp.elements() {
+            match x {
+                ExpressionElement::CALL(name, _) if !self.info.calls.contains(name) => {
+                    self.info.calls.push(name.clone());
+                }
+                ExpressionElement::BUILTIN(builtin) if builtin.is_random() => self.info.random = true,
+                _ => {}
+            }
+        }
+    }
+
+    pub fn push_loop(&mut self, value: Loop) {
+        self.info.loops.push(value);
+    }
+
+    pub fn take_info(&mut self) -> FunctionInfo {
+        std::mem::take(&mut self.info)
     }
 }
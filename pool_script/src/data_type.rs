@@ -0,0 +1,143 @@
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
+use std::io::{Error, ErrorKind};
+
+/// The type of the data slots and the local vars
+///
+/// All the values are f32 in the calc stack. The vec2 takes two slots named `<name>.x` and `<name>.y`
+/// and the str is the index of the interned string in the script.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+#[repr(u8)]
+pub enum DataType {
+    #[default]
+    F32 = 0,
+    I32 = 1,
+    Bool = 2,
+    Vec2 = 3,
+    Str = 4,
+}
+
+impl DataType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DataType::F32 => "f32",
+            DataType::I32 => "i32",
+            DataType::Bool => "bool",
+            DataType::Vec2 => "vec2",
+            DataType::Str => "str",
+        }
+    }
+
+    pub fn is_number(&self) -> bool {
+        matches!(self, DataType::F32 | DataType::I32)
+    }
+
+    /// The value of the type could be stored in the var of this type
+    pub fn accept(&self, ty: DataType) -> bool {
+        *self == ty || (*self == DataType::F32 && ty == DataType::I32)
+    }
+
+    /// The names of the slots taken by the var
+    pub fn slot_names(&self, name: &str) -> Vec<String> {
+        if *self == DataType::Vec2 {
+            vec![format!("{}.x", name), format!("{}.y", name)]
+        } else {
+            vec![name.to_string()]
+        }
+    }
+}
+
+impl Display for DataType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl TryFrom<u8> for DataType {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(DataType::F32),
+            1 => Ok(DataType::I32),
+            2 => Ok(DataType::Bool),
+            3 => Ok(DataType::Vec2),
+            4 => Ok(DataType::Str),
+            _ => Err(Error::new(ErrorKind::InvalidData, format!("[parse data type]no such data type {}", value)))
+        }
+    }
+}
+
+impl TryFrom<&str> for DataType {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "f32" => Ok(DataType::F32),
+            "i32" => Ok(DataType::I32),
+            "bool" => Ok(DataType::Bool),
+            "vec2" => Ok(DataType::Vec2),
+            "str" => Ok(DataType::Str),
+            _ => Err(Error::new(ErrorKind::InvalidData, "[parse data type]expected data type but found : ".to_owned() + value))
+        }
+    }
+}
+
+/// The value in the data slot of the script
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DataValue {
+    F32(f32),
+    I32(i32),
+    Bool(bool),
+    Str(u32),
+}
+
+impl DataValue {
+    /// The vec2 slot keeps the f32
+    pub fn new(ty: DataType, value: f32) -> Self {
+        let mut data = match ty {
+            DataType::F32 | DataType::Vec2 => DataValue::F32(0.0),
+            DataType::I32 => DataValue::I32(0),
+            DataType::Bool => DataValue::Bool(false),
+            DataType::Str => DataValue::Str(0),
+        };
+        data.set(value);
+        data
+    }
+
+    pub fn get(&self) -> f32 {
+        match self {
+            DataValue::F32(value) => *value,
+            DataValue::I32(value) => *value as f32,
+            DataValue::Bool(value) => if *value { 1.0 } else { 0.0 },
+            DataValue::Str(value) => *value as f32,
+        }
+    }
+
+    /// Convert the value to the type of the slot
+    pub fn set(&mut self, value: f32) {
+        match self {
+            DataValue::F32(x) => *x = value,
+            DataValue::I32(x) => *x = value as i32,
+            DataValue::Bool(x) => *x = value != 0.0,
+            DataValue::Str(x) => *x = value as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data_type::{DataType, DataValue};
+
+    #[test]
+    fn convert_when_storing() {
+        assert_eq!(DataValue::new(DataType::I32, -2.7), DataValue::I32(-2));
+        assert_eq!(DataValue::new(DataType::Bool, 0.5).get(), 1.0);
+        assert_eq!(DataValue::new(DataType::Vec2, 0.5), DataValue::F32(0.5));
+        let mut value = DataValue::new(DataType::Str, 3.0);
+        value.set(1.0);
+        assert_eq!(value, DataValue::Str(1));
+        assert!(DataType::F32.accept(DataType::I32));
+        assert!(!DataType::I32.accept(DataType::F32));
+    }
+}
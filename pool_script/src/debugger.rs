@@ -0,0 +1,97 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use crate::data_type::DataValue;
+use crate::disasm::Disassembler;
+use crate::manager::ScriptDesc;
+use crate::pool_script::FunctionDesc;
+use crate::vm::CalcStack;
+
+/// Pauses the tick function of an object to inspect its script
+///
+/// The paused function returns like `wait` and continues from the same command in the later ticks.
+/// The functions called by it and the callbacks are not paused.
+/// The calc stack is not kept by the paused function, so it only pauses between the statements.
+#[derive(Debug, Default)]
+pub struct ScriptDebug {
+    pub object_id: u64,
+    /// the offsets of the commands in the tick function to pause at
+    pub breakpoints: HashSet<usize>,
+    /// pause at every statement
+    step: bool,
+    /// run the command paused at once
+    resume: bool,
+    /// a breakpoint inside the statement, it pauses at the next statement
+    pending: bool,
+    paused: Option<usize>,
+}
+
+impl ScriptDebug {
+    /// Pause at the next command
+    pub fn new(object_id: u64, breakpoints: HashSet<usize>) -> Self {
+        Self {
+            object_id,
+            breakpoints,
+            step: true,
+            ..Default::default()
+        }
+    }
+
+    /// Run the paused statement and pause at the next one
+    pub fn step(&mut self) {
+        self.step = true;
+        self.resume = true;
+        self.paused = None;
+    }
+
+    /// Run until the next breakpoint
+    pub fn resume(&mut self) {
+        self.step = false;
+        self.resume = true;
+        self.paused = None;
+    }
+
+    /// Return true if the command should wait, the state is dumped when it starts to pause
+    pub(crate) fn pause_at(&mut self, offset: usize, state: DebugState) -> bool {
+        if std::mem::take(&mut self.resume) {
+            return false;
+        }
+        self.pending |= self.breakpoints.contains(&offset);
+        if !(self.step || self.pending) || !state.calc_stack.values().is_empty() {
+            return false;
+        }
+        self.pending = false;
+        if self.paused != Some(offset) {
+            self.paused = Some(offset);
+            log::info!("[debug]object {} paused at {}", self.object_id, state.dump(offset));
+        }
+        true
+    }
+}
+
+/// The state of the running function
+pub(crate) struct DebugState<'a> {
+    pub script: &'a ScriptDesc,
+    pub function: &'a FunctionDesc,
+    pub var_stack: &'a [f32],
+    pub data: &'a [DataValue],
+    pub calc_stack: &'a CalcStack,
+}
+
+impl<'a> DebugState<'a> {
+    fn dump(&self, offset: usize) -> String {
+        let command = Disassembler::new(self.function, &self.script.data_index, &self.script.strings)
+            .command(offset)
+            .map_or_else(|e| e.to_string(), |(command, _)| command);
+        let mut text = format!("{}::tick {:04}  {}", self.script.name, offset, command);
+        let _ = write!(text, "\n  stack: {:?}", self.var_stack);
+        let mut data = self.script.data_index.iter().collect::<Vec<_>>();
+        data.sort_by_key(|(_, idx)| **idx);
+        let data = data.iter()
+            .map(|(name, idx)| format!("{} = {}", name, self.data[**idx as usize].get()))
+            .collect::<Vec<_>>();
+        let _ = write!(text, "\n  data: [{}]", data.join(", "));
+        let _ = write!(text, "\n  calc: {:?}", self.calc_stack.values());
+        text
+    }
+}
@@ -0,0 +1,556 @@
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::fmt::Write;
+use std::io::{Error, ErrorKind};
+
+use crate::builtin::Builtin;
+use crate::data_type::DataType;
+use crate::game_data::{GameData, ItemKind};
+use crate::pool_script::{DYNAMIC_NAME, PoolScriptBin};
+
+const INDENT: &str = "    ";
+
+/// The priority of a value which never needs brackets
+const LEAF_PRIORITY: u8 = u8::MAX;
+
+/// Turn the script loaded by [`PoolScriptBin::try_parse_bin`] back into the PoolScript source
+///
+/// The local vars are named by their stack index because the names are not in the binary.
+/// The param count of the functions is not in the binary too, so it is got from the calls.
+/// The locals are always f32 because their types are not in the binary either.
+pub fn decompile(bin: &PoolScriptBin) -> Result<String, Error> {
+    let mut source = String::new();
+    let mut data = bin.data.iter().collect::<Vec<_>>();
+    data.sort_by_key(|(_, idx)| **idx);
+    let data_names = data.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+    if !data_names.is_empty() {
+        source.push_str("data\n");
+        for (idx, name) in data_names.iter().enumerate() {
+            let ty = bin.data_types.get(idx).copied().unwrap_or_default();
+            //the vec2 is declared once for both slots
+            let name = match (ty, name.strip_suffix(".x")) {
+                (DataType::Vec2, Some(name)) => name,
+                (DataType::Vec2, None) => continue,
+                _ => name,
+            };
+            let _ = writeln!(source, "{}{} {}", INDENT, ty, name);
+        }
+        source.push_str("end\n\n");
+    }
+
+    let mut functions = bin.functions.iter().collect::<Vec<_>>();
+    functions.sort_by(|a, b| a.0.cmp(b.0));
+    let mut params = HashMap::new();
+    for (name, function) in &functions {
+        let (_, calls) = decompile_function(name, &function.code, bin, &data_names, 0)?;
+        for (name, args) in calls {
+            let count = params.entry(name).or_insert(0);
+            *count = args.max(*count);
+        }
+    }
+    for (name, function) in functions {
+        let param_count = params.get(name.as_str()).copied().unwrap_or(0);
+        source.push_str("function ");
+        source.push_str(name);
+        if param_count > 0 {
+            let names = (0..param_count).map(local_name).collect::<Vec<_>>();
+            let _ = write!(source, "({})", names.join(", "));
+        }
+        source.push('\n');
+        source.push_str(&decompile_function(name, &function.code, bin, &data_names, param_count)?.0);
+        source.push('\n');
+    }
+    Ok(source)
+}
+
+/// Return the source of the function body and the arg count of the calls
+fn decompile_function(name: &str, code: &[u8], bin: &PoolScriptBin, data_names: &[&str], params: u8) -> Result<(String, HashMap<String, u8>), Error> {
+    let mut source = String::new();
+    let mut decompiler = FunctionDecompiler {
+        code,
+        pointer: 0,
+        data_names,
+        data_types: &bin.data_types,
+        strings: &bin.strings,
+        calc_stack: vec![],
+        locals: params,
+        scopes: vec![],
+        calls: HashMap::new(),
+        source: &mut source,
+    };
+    decompiler.decompile().map_err(|e| {
+        Error::new(ErrorKind::InvalidData, format!("[decompile]function {}: {}", name, e))
+    })?;
+    let calls = decompiler.calls;
+    Ok((source, calls))
+}
+
+struct FunctionDecompiler<'a> {
+    code: &'a [u8],
+    pointer: usize,
+    data_names: &'a [&'a str],
+    data_types: &'a [DataType],
+    strings: &'a [String],
+    /// the expressions with the priority of their top operator
+    calc_stack: Vec<(String, u8)>,
+    /// the count of the allocated local vars
+    locals: u8,
+    scopes: Vec<Scope>,
+    calls: HashMap<String, u8>,
+    source: &'a mut String,
+}
+
+struct Scope {
+    /// the count of the local vars before the block
+    locals: u8,
+    /// where the if block ends, none for the loop
+    end: Option<usize>,
+}
+
+impl<'a> FunctionDecompiler<'a> {
+    fn decompile(&mut self) -> Result<(), Error> {
+        loop {
+            self.close_if()?;
+            let command = self.read_byte()?;
+            match command {
+                0 => {
+                    match self.scopes.pop() {
+                        Some(Scope { locals, end: None }) => {
+                            self.locals = locals;
+                            self.push_line("end".into());
+                        }
+                        Some(_) => {
+                            return Err(Error::new(ErrorKind::InvalidData, format!("if is not end before {}", self.pointer - 1)));
+                        }
+                        None => {
+                            self.source.push_str("end\n");
+                            break;
+                        }
+                    }
+                }
+                1 => {
+                    self.push_line("loop".into());
+                    self.scopes.push(Scope { locals: self.locals, end: None });
+                }
+                2 => {
+                    if self.code.get(self.pointer) == Some(&9) {
+                        self.pointer += 1;
+                        self.push_line("return".into());
+                    } else {
+                        let value = self.read_value(true)?;
+                        self.push_line(format!("return {}", value));
+                    }
+                }
+                3 => {
+                    let value = self.read_value(false)?;
+                    self.calc_stack.push((value, LEAF_PRIORITY));
+                }
+                7 => {
+                    let cond = self.read_value(true)?;
+                    let offset = self.read_offset()?;
+                    self.push_line(format!("if {}", cond));
+                    self.scopes.push(Scope { locals: self.locals, end: Some(self.pointer + offset) });
+                }
+                8 => {
+                    let offset = self.read_offset()?;
+                    //only the jump at the end of the if block is generated by the compiler
+                    match self.scopes.pop() {
+                        Some(Scope { locals, end: Some(end) }) if end == self.pointer => {
+                            self.locals = locals;
+                            self.push_line("else".into());
+                            self.scopes.push(Scope { locals, end: Some(self.pointer + offset) });
+                        }
+                        _ => {
+                            return Err(Error::new(ErrorKind::InvalidData, format!("unexpected jump at {}", self.pointer - 3)));
+                        }
+                    }
+                }
+                5 | 6 | 10 => {
+                    let value = self.read_value(true)?;
+                    let command = match command {
+                        5 => "break",
+                        6 => "wait",
+                        _ => "move_up",
+                    };
+                    self.push_line(format!("{} {}", command, value));
+                }
+                11 | 12 => {
+                    let line = self.read_summon(command)?;
+                    self.push_line(line);
+                }
+                13 => {
+                    let name = self.read_str()?;
+                    let count = self.read_byte()?;
+                    let mut args = Vec::with_capacity(count as usize);
+                    for _ in 0..count {
+                        args.push(self.pop_calc()?.0);
+                    }
+                    args.reverse();
+                    self.calc_stack.push((format!("call {}({})", name, args.join(", ")), LEAF_PRIORITY));
+                    self.calls.insert(name, count);
+                }
+                14 => {
+                    let (value, _) = self.pop_calc()?;
+                    if !value.starts_with("call ") {
+                        return Err(Error::new(ErrorKind::InvalidData, format!("drop the value which is not returned at {}", self.pointer - 1)));
+                    }
+                    self.push_line(value);
+                }
+                16 => self.push_line("kill".into()),
+                17 => {
+                    let count = self.read_value(true)?;
+                    let kind = ItemKind::try_from(self.read_byte()?)?;
+                    self.push_line(format!("drop_item {} {}", kind.name(), count));
+                }
+                20 => {
+                    let (value, _) = self.pop_calc()?;
+                    let src = self.read_byte()?;
+                    let idx = self.read_byte()?;
+                    if src == 3 && idx >= self.locals {
+                        self.allocate(idx);
+                        self.locals = idx + 1;
+                    }
+                    let name = self.pointer_name(src, idx)?;
+                    let value = if src == 2 { self.typed_const(idx, value) } else { value };
+                    self.push_line(format!("let {} = {}", name, value));
+                }
+                21..=31 => {
+                    let (right, right_priority) = self.pop_calc()?;
+                    let (left, left_priority) = self.pop_calc()?;
+                    let (op, priority) = operator(command);
+                    let left = if left_priority < priority {
+                        format!("({})", left)
+                    } else {
+                        left
+                    };
+                    let right = if right_priority <= priority {
+                        format!("({})", right)
+                    } else {
+                        right
+                    };
+                    self.calc_stack.push((format!("{} {} {}", left, op, right), priority));
+                }
+                41..=56 => {
+                    let builtin = Builtin::try_from(command)?;
+                    let mut args = Vec::with_capacity(builtin.get_args_count());
+                    for _ in 0..builtin.get_args_count() {
+                        args.push(self.pop_calc()?.0);
+                    }
+                    args.reverse();
+                    self.calc_stack.push((format!("{}({})", builtin.name(), args.join(", ")), LEAF_PRIORITY));
+                }
+                38 | 39 => {
+                    let src = self.read_value(true)?;
+                    let dst_src = self.read_byte()?;
+                    let dst_idx = self.read_byte()?;
+                    let dst = self.use_pointer(dst_src, dst_idx)?;
+                    self.push_line(format!("{} {}, {}", if command == 38 { "sin" } else { "cos" }, src, dst));
+                }
+                _ => {
+                    return Err(Error::new(ErrorKind::InvalidData, format!("unsupported command {} at {}", command, self.pointer - 1)));
+                }
+            }
+        }
+        if !self.calc_stack.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData, "calc stack is not balance"));
+        }
+        Ok(())
+    }
+
+    /// End the if blocks which end here
+    fn close_if(&mut self) -> Result<(), Error> {
+        while let Some(Scope { locals, end: Some(end) }) = self.scopes.last() {
+            if *end > self.pointer {
+                break;
+            }
+            if *end < self.pointer {
+                return Err(Error::new(ErrorKind::InvalidData, format!("jump into the command at {}", end)));
+            }
+            self.locals = *locals;
+            self.scopes.pop();
+            self.push_line("end".into());
+        }
+        Ok(())
+    }
+
+    fn push_line(&mut self, line: String) {
+        for _ in 0..self.scopes.len() + 1 {
+            self.source.push_str(INDENT);
+        }
+        let _ = writeln!(self.source, "{}", line);
+    }
+
+    fn pop_calc(&mut self) -> Result<(String, u8), Error> {
+        self.calc_stack.pop()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("pop empty calc stack at {}", self.pointer)))
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Error> {
+        let byte = self.code.get(self.pointer)
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "function is not end"))?;
+        self.pointer += 1;
+        Ok(*byte)
+    }
+
+    fn read_offset(&mut self) -> Result<usize, Error> {
+        Ok(u16::from_be_bytes([self.read_byte()?, self.read_byte()?]) as usize)
+    }
+
+    fn read_str(&mut self) -> Result<String, Error> {
+        let len = u16::from_be_bytes([self.read_byte()?, self.read_byte()?]) as usize;
+        let bytes = self.code.get(self.pointer..self.pointer + len)
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "str is not end"))?;
+        self.pointer += len;
+        String::from_utf8(bytes.to_vec()).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Read the pointer value as source
+    /// The value popped from the calc stack is only allowed if `pop` is true
+    fn read_value(&mut self, pop: bool) -> Result<String, Error> {
+        let src = self.read_byte()?;
+        match src {
+            0 => {
+                let bytes = self.code.get(self.pointer..self.pointer + 4)
+                    .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "const is not end"))?;
+                self.pointer += 4;
+                let value = f32::from_be_bytes(bytes.try_into().unwrap());
+                if !value.is_finite() {
+                    return Err(Error::new(ErrorKind::InvalidData, format!("const value {} cannot be written", value)));
+                }
+                Ok(value.to_string())
+            }
+            4 if pop => Ok(self.pop_calc()?.0),
+            1..=3 => {
+                let idx = self.read_byte()?;
+                self.use_pointer(src, idx)
+            }
+            _ => Err(Error::new(ErrorKind::InvalidData, format!("unsupported pointer {} at {}", src, self.pointer - 1)))
+        }
+    }
+
+    /// Declare the allocated but not stored local vars before `idx`
+    /// The allocate command is dropped when loading so we cannot know where they are declared
+    fn allocate(&mut self, idx: u8) {
+        for x in self.locals..idx {
+            self.push_line(format!("let {}", local_name(x)));
+        }
+    }
+
+    fn use_pointer(&mut self, src: u8, idx: u8) -> Result<String, Error> {
+        if src == 3 && idx >= self.locals {
+            self.allocate(idx + 1);
+            self.locals = idx + 1;
+        }
+        self.pointer_name(src, idx)
+    }
+
+    /// The const stored to the str or bool data is written as the literal
+    fn typed_const(&self, idx: u8, value: String) -> String {
+        let literal = match (self.data_types.get(idx as usize), value.parse::<u32>()) {
+            (Some(DataType::Str), Ok(x)) => self.strings.get(x as usize).map(|x| format!("\"{}\"", x)),
+            (Some(DataType::Bool), Ok(x)) if x <= 1 => Some((x == 1).to_string()),
+            _ => None
+        };
+        literal.unwrap_or(value)
+    }
+
+    fn pointer_name(&self, src: u8, idx: u8) -> Result<String, Error> {
+        match src {
+            1 => game_data_name(idx).map(|x| x.to_string())
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("unknown game data {}", idx))),
+            2 => self.data_names.get(idx as usize).map(|x| x.to_string())
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("unknown data {}", idx))),
+            3 => Ok(local_name(idx)),
+            _ => Err(Error::new(ErrorKind::InvalidData, format!("unsupported pointer {}", src)))
+        }
+    }
+
+    /// The literal name or `(<value>)` for the str value
+    fn read_name(&mut self) -> Result<String, Error> {
+        if self.code.get(self.pointer..self.pointer + 2) != Some(&DYNAMIC_NAME.to_be_bytes()) {
+            return self.read_str();
+        }
+        self.pointer += 2;
+        if self.code.get(self.pointer) == Some(&0) {
+            let value = self.read_value(false)?;
+            let string = value.parse::<usize>().ok().and_then(|x| self.strings.get(x))
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("no string {}", value)))?;
+            Ok(format!("(\"{}\")", string))
+        } else {
+            Ok(format!("({})", self.read_value(false)?))
+        }
+    }
+
+    fn read_summon(&mut self, command: u8) -> Result<String, Error> {
+        let mut line = String::from(if command == 11 { "summon_e " } else { "summon_b " });
+        line.push_str(&self.read_name()?);
+        //xyz hp or xyz scale angle
+        for _ in 0..if command == 11 { 4 } else { 5 } {
+            line.push(' ');
+            line.push_str(&self.read_value(false)?);
+        }
+        let collide = self.read_byte()?;
+        match collide {
+            10 => {
+                line.push_str(" circle ");
+                line.push_str(&self.read_value(false)?);
+            }
+            _ => return Err(Error::new(ErrorKind::InvalidData, format!("unknown collide {}", collide)))
+        }
+        line.push(' ');
+        line.push_str(&self.read_name()?);
+        while self.code.get(self.pointer) != Some(&9) {
+            line.push(' ');
+            line.push_str(&self.read_value(false)?);
+        }
+        self.pointer += 1;
+        Ok(line)
+    }
+}
+
+fn local_name(idx: u8) -> String {
+    format!("var{}", idx)
+}
+
+fn game_data_name(idx: u8) -> Option<&'static str> {
+    if idx > GameData::PlayerGraze as u8 {
+        return None;
+    }
+    GameData::try_from(idx).ok().filter(GameData::is_var).map(|x| x.name())
+}
+
+fn operator(command: u8) -> (&'static str, u8) {
+    match command {
+        21 => ("+", 2),
+        22 => ("-", 2),
+        23 => ("*", 3),
+        24 => ("/", 3),
+        25 => ("%", 3),
+        26 => ("==", 1),
+        27 => ("!=", 1),
+        28 => ("<", 1),
+        29 => (">", 1),
+        30 => ("<=", 1),
+        _ => (">=", 1),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::BufReader;
+
+    use crate::decompile::decompile;
+    use crate::pool_script::{Parser, PoolScriptBin};
+
+    fn compile(source: &str) -> Vec<u8> {
+        let bin = Parser::new(source.as_bytes()).try_parse().unwrap();
+        let mut binary = vec![];
+        bin.save(&mut binary).unwrap();
+        binary
+    }
+
+    fn round_trip(binary: &[u8]) -> Vec<u8> {
+        let bin = PoolScriptBin::try_parse_bin(BufReader::new(binary)).unwrap();
+        compile(&decompile(&bin).unwrap())
+    }
+
+    #[test]
+    fn decompile_round_trip() {
+        let source = "data
+f32 speed
+f32 angle
+i32 count
+vec2 target
+bool alive
+str texture
+end
+
+function tick
+    let a = -2 * speed * (2 + pos_x) - 1.5
+    let b = 0
+    loop
+        let c = a % 3
+        break c >= 2
+        wait 1
+        move_up speed
+        loop
+            let d = (a - c) / (player_x - pos_x)
+            let g = atan2(player_y - pos_y, player_x - pos_x) - player_angle() + lerp(a, speed, 0.5) * min(c, -2)
+            move_up rand_range(-1, rand()) * rand_int(3) + floor(g % 3) + max(abs(d), pow(2, sqrt(d))) - clamp(cos(g), 0, sin(d))
+            break 1
+        end
+        sin angle * 2 + a, b
+        if b > 0.5
+            let e = b * 2
+            cos -90, e
+        else
+            cos -90, a
+            if a == 0
+                drop_item power 2
+                kill
+            end
+        end
+        summon_b circle_blue pos_x pos_y 0 0.3 angle circle 1.5 normal 5 speed
+    end
+    let b = (a + b) * 0 - angle
+    move_up (b)
+    if pos_x < player_x
+        let f = 1
+        move_up f
+    end
+    summon_e 暗夜 0 300 0 1000 circle 50 sp1
+    let count = count * 2 + rand_int(3) - enemy_count * stage_tick + player_graze
+    if player_focused
+        move_up self_speed + self_hp + self_age + pos_z + player_z
+    end
+    let target.x = player_x
+    let alive = true
+    let alive = target.y <= pos_y
+    let texture = \"红\"
+    summon_b (texture) pos_x pos_y 0 0.3 angle circle 1.5 (\"normal\") 5 speed
+    kill
+end
+
+function on_die
+    wait speed
+    call fire(call aim(pos_x, 1) * 2, speed)
+    return
+end
+
+function aim(x, y)
+    if x > player_x
+        return -(player_x - x) / y
+    end
+    return 0
+end
+
+function fire(a, s)
+    summon_b circle_blue pos_x pos_y 0 0.3 a circle 1.5 normal s
+    return a + call aim(a, s)
+end
+";
+        let binary = compile(source);
+        assert_eq!(binary, round_trip(&binary));
+    }
+
+    #[test]
+    fn decompile_shipped_scripts() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../pooltouhou/res/script");
+        for entry in dir.read_dir().unwrap() {
+            let path = entry.unwrap().path();
+            //the golden outputs are in the same dir
+            if path.extension() != Some("pthpsb".as_ref()) {
+                continue;
+            }
+            let binary = std::fs::read(&path).unwrap();
+            //the allocate commands may be moved, so compare the loaded functions
+            let origin = PoolScriptBin::try_parse_bin(BufReader::new(&binary[..])).unwrap();
+            let decompiled = round_trip(&binary);
+            let decompiled = PoolScriptBin::try_parse_bin(BufReader::new(&decompiled[..])).unwrap();
+            assert_eq!(origin.data.len(), decompiled.data.len(), "data of {:?}", path);
+            for (name, function) in origin.functions {
+                assert_eq!(function.code, decompiled.functions[&name].code, "function {} of {:?}", name, path);
+            }
+        }
+    }
+}
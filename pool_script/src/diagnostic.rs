@@ -0,0 +1,142 @@
+use std::fmt::{Display, Formatter};
+use std::io::Error;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// The location of the source text
+///
+/// `line` starts from 1 and `column` starts from 0 (in bytes), line 0 means unknown.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub span: Span,
+    pub message: String,
+    pub notes: Vec<String>,
+}
+
+impl Span {
+    pub fn new(column: usize, len: usize) -> Self {
+        Self {
+            line: 0,
+            column,
+            len,
+        }
+    }
+
+    /// The span from self to the end of other if they are in the same line
+    pub fn to(self, other: Span) -> Span {
+        if self.line == other.line && other.column >= self.column {
+            Span {
+                len: other.column + other.len - self.column,
+                ..self
+            }
+        } else {
+            self
+        }
+    }
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            span: Default::default(),
+            message: message.into(),
+            notes: vec![],
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(message)
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Render the diagnostic with a caret under the source text
+    pub fn render(&self, file: &str, source: &str) -> String {
+        let mut result = format!("{}\n", self);
+        if self.span.line == 0 {
+            result.push_str(&format!(" --> {}\n", file));
+        } else {
+            result.push_str(&format!(" --> {}:{}:{}\n", file, self.span.line, self.span.column + 1));
+            if let Some(text) = source.lines().nth(self.span.line - 1) {
+                let line_no = self.span.line.to_string();
+                let pad = " ".repeat(line_no.len());
+                result.push_str(&format!("{} |\n", pad));
+                result.push_str(&format!("{} | {}\n", line_no, text));
+                //keep the tabs so the caret is under the text
+                let prefix = text.get(..self.span.column.min(text.len())).unwrap_or("")
+                    .chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect::<String>();
+                let width = text.get(self.span.column.min(text.len())..(self.span.column + self.span.len).min(text.len()))
+                    .map(|x| x.chars().count()).unwrap_or(0).max(1);
+                result.push_str(&format!("{} | {}{}\n", pad, prefix, "^".repeat(width)));
+            }
+        }
+        for note in &self.notes {
+            result.push_str(&format!(" = note: {}\n", note));
+        }
+        result
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error: {}", self.message),
+            Severity::Warning => write!(f, "warning: {}", self.message),
+        }
+    }
+}
+
+impl From<Error> for Diagnostic {
+    fn from(e: Error) -> Self {
+        Self::error(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::diagnostic::{Diagnostic, Span};
+
+    #[test]
+    fn render_caret() {
+        let source = "function tick\n    move_up speeed\nend\n";
+        let mut diagnostic = Diagnostic::error("unknown var name `speeed`")
+            .with_span(Span::new(12, 6))
+            .with_note("declare it in the data block");
+        diagnostic.span.line = 2;
+        assert_eq!(diagnostic.render("a.pthps", source), "error: unknown var name `speeed`
+ --> a.pthps:2:13
+  |
+2 |     move_up speeed
+  |             ^^^^^^
+ = note: declare it in the data block
+");
+    }
+}
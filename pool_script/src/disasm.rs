@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Write;
+use std::io::{Error, ErrorKind};
+
+use crate::builtin::Builtin;
+use crate::game_data::{GameData, ItemKind};
+use crate::pool_script::{DYNAMIC_NAME, FunctionDesc, PoolScriptBin};
+
+/// Print all the functions of the script, one command with its offset in a line
+pub fn disassemble(bin: &PoolScriptBin) -> Result<String, Error> {
+    let mut functions = bin.functions.iter().collect::<Vec<_>>();
+    functions.sort_by(|a, b| a.0.cmp(b.0));
+    let mut text = String::new();
+    for (name, function) in functions {
+        let _ = writeln!(text, "function {} (stack {}, calc {}{})", name, function.max_stack, function.max_calc,
+                         if function.thread_safe { ", thread safe" } else { "" });
+        text.push_str(&Disassembler::new(function, &bin.data, &bin.strings).disassemble()?);
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/// Decode the commands of a function
+///
+/// The operands are printed as `const 1`, `game pos_x`, `data speed`, `stack 0` and `pop` for the calc stack.
+pub struct Disassembler<'a> {
+    code: &'a [u8],
+    data_names: Vec<&'a str>,
+    strings: &'a [String],
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(function: &'a FunctionDesc, data: &'a HashMap<String, u8>, strings: &'a [String]) -> Self {
+        let mut data = data.iter().collect::<Vec<_>>();
+        data.sort_by_key(|(_, idx)| **idx);
+        Self {
+            code: &function.code,
+            data_names: data.into_iter().map(|(name, _)| name.as_str()).collect(),
+            strings,
+        }
+    }
+
+    /// All the commands with their offsets
+    pub fn disassemble(&self) -> Result<String, Error> {
+        let mut text = String::new();
+        let mut offset = 0;
+        while offset < self.code.len() {
+            let (command, next) = self.command(offset)?;
+            let _ = writeln!(text, "{:04}  {}", offset, command);
+            offset = next;
+        }
+        Ok(text)
+    }
+
+    /// Decode the command at the offset, return it and the offset of the next command
+    pub fn command(&self, offset: usize) -> Result<(String, usize), Error> {
+        let mut reader = Reader {
+            disassembler: self,
+            pointer: offset,
+        };
+        let command = reader.read_byte()?;
+        let text = match command {
+            0 => "end".to_string(),
+            1 => "loop".to_string(),
+            2 => format!("ret {}", reader.read_pointer()?),
+            3 => format!("push {}", reader.read_pointer()?),
+            5 => format!("break {}", reader.read_pointer()?),
+            6 => format!("wait {}", reader.read_pointer()?),
+            7 => {
+                let cond = reader.read_pointer()?;
+                let offset = reader.read_offset()?;
+                format!("jump_unless {} -> {:04}", cond, reader.pointer + offset)
+            }
+            8 => {
+                let offset = reader.read_offset()?;
+                format!("jump -> {:04}", reader.pointer + offset)
+            }
+            10 => format!("move_up {}", reader.read_pointer()?),
+            11 | 12 => reader.read_summon(command)?,
+            13 => {
+                let name = reader.read_str()?;
+                format!("call {} {}", name, reader.read_byte()?)
+            }
+            14 => "pop".to_string(),
+            16 => "kill".to_string(),
+            17 => {
+                let count = reader.read_pointer()?;
+                format!("drop_item {} {}", ItemKind::try_from(reader.read_byte()?)?.name(), count)
+            }
+            20 => format!("store {}", reader.read_pointer()?),
+            21..=31 => operator(command).to_string(),
+            38 | 39 => {
+                let src = reader.read_pointer()?;
+                let dst = reader.read_pointer()?;
+                format!("{} {} -> {}", if command == 38 { "sin" } else { "cos" }, src, dst)
+            }
+            41..=56 => Builtin::try_from(command)?.name().to_string(),
+            _ => return Err(reader.error(format!("unknown command {}", command)))
+        };
+        Ok((text, reader.pointer))
+    }
+}
+
+struct Reader<'a, 'b> {
+    disassembler: &'b Disassembler<'a>,
+    pointer: usize,
+}
+
+impl<'a, 'b> Reader<'a, 'b> {
+    fn error<S: AsRef<str>>(&self, msg: S) -> Error {
+        Error::new(ErrorKind::InvalidData, format!("[disassemble]{} at {}", msg.as_ref(), self.pointer))
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let code = self.disassembler.code;
+        let bytes = code.get(self.pointer..self.pointer + len)
+            .ok_or_else(|| self.error("unexpected end of function"))?;
+        self.pointer += len;
+        Ok(bytes)
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Error> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_offset(&mut self) -> Result<usize, Error> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+    }
+
+    fn read_str(&mut self) -> Result<&'a str, Error> {
+        let len = self.read_offset()?;
+        let bytes = self.read_bytes(len)?;
+        std::str::from_utf8(bytes).map_err(|_| self.error("the string is not utf8"))
+    }
+
+    fn read_pointer(&mut self) -> Result<String, Error> {
+        let src = self.read_byte()?;
+        Ok(match src {
+            0 => {
+                let bytes = self.read_bytes(4)?;
+                format!("const {}", f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            }
+            1 => {
+                let idx = self.read_byte()?;
+                match GameData::try_from(idx) {
+                    Ok(game_data) => format!("game {}", game_data.name()),
+                    Err(_) => format!("game #{}", idx),
+                }
+            }
+            2 => {
+                let idx = self.read_byte()?;
+                match self.disassembler.data_names.get(idx as usize) {
+                    Some(name) => format!("data {}", name),
+                    None => format!("data #{}", idx),
+                }
+            }
+            3 => format!("stack {}", self.read_byte()?),
+            4 => "pop".to_string(),
+            9 => "none".to_string(),
+            _ => return Err(self.error(format!("unknown data src {}", src)))
+        })
+    }
+
+    /// The literal name in quotes or the pointer to the str value in brackets
+    fn read_name(&mut self) -> Result<String, Error> {
+        if self.disassembler.code.get(self.pointer..self.pointer + 2) != Some(&DYNAMIC_NAME.to_be_bytes()) {
+            return Ok(format!("\"{}\"", self.read_str()?));
+        }
+        self.pointer += 2;
+        let pointer = self.read_pointer()?;
+        let string = pointer.strip_prefix("const ").and_then(|x| x.parse::<usize>().ok())
+            .and_then(|x| self.disassembler.strings.get(x));
+        Ok(match string {
+            Some(string) => format!("({} \"{}\")", pointer, string),
+            None => format!("({})", pointer),
+        })
+    }
+
+    fn read_summon(&mut self, command: u8) -> Result<String, Error> {
+        let mut text = String::from(if command == 11 { "summon_e " } else { "summon_b " });
+        text.push_str(&self.read_name()?);
+        for _ in 0..if command == 11 { 4 } else { 5 } {
+            text.push_str(", ");
+            text.push_str(&self.read_pointer()?);
+        }
+        let collide = self.read_byte()?;
+        if collide != GameData::CircleCollide as u8 {
+            return Err(self.error(format!("unknown collide {}", collide)));
+        }
+        text.push_str(", circle(");
+        let args = (0..GameData::CircleCollide.get_args_count())
+            .map(|_| self.read_pointer())
+            .collect::<Result<Vec<_>, _>>()?;
+        text.push_str(&args.join(", "));
+        text.push_str("), ");
+        text.push_str(&self.read_name()?);
+        let mut args = vec![];
+        while self.disassembler.code.get(self.pointer) != Some(&9) {
+            args.push(self.read_pointer()?);
+        }
+        self.pointer += 1;
+        let _ = write!(text, " [{}]", args.join(", "));
+        Ok(text)
+    }
+}
+
+fn operator(command: u8) -> &'static str {
+    match command {
+        21 => "add",
+        22 => "sub",
+        23 => "mul",
+        24 => "div",
+        25 => "mod",
+        26 => "eq",
+        27 => "neq",
+        28 => "lt",
+        29 => "gt",
+        30 => "le",
+        _ => "ge",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::disasm::{disassemble, Disassembler};
+    use crate::pool_script::Parser;
+
+    #[test]
+    fn print_commands() {
+        let source = "data
+    f32 speed
+    str tex
+end
+
+function tick
+    let a = speed * 2
+    if a > 1
+        summon_b (tex) 0 0 0 1 a circle 5 normal 1
+    end
+    wait 1
+end
+";
+        let bin = Parser::new(source.as_bytes()).try_parse().unwrap();
+        assert_eq!(disassemble(&bin).unwrap(), "function tick (stack 1, calc 2, thread safe)
+0000  push data speed
+0003  push const 2
+0009  mul
+0010  store stack 0
+0013  push stack 0
+0016  push const 1
+0022  gt
+0023  jump_unless pop -> 0074
+0027  summon_b (data tex), const 0, const 0, const 0, const 1, stack 0, circle(const 5), \"normal\" [const 1]
+0074  wait const 1
+0080  end
+
+");
+
+        let function = &bin.functions["tick"];
+        let disassembler = Disassembler::new(function, &bin.data, &bin.strings);
+        assert_eq!(disassembler.command(0).unwrap(), ("push data speed".to_string(), 3));
+        assert!(disassembler.command(81).is_err());
+    }
+}
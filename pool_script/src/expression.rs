@@ -1,7 +1,13 @@
 use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
 use std::io::{Error, ErrorKind};
 
+use crate::ast::{AstParser, Expr};
+use crate::builtin::Builtin;
 use crate::context::Context;
+use crate::data_type::DataType;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::lexer::{tokenize, TokenKind};
 use crate::pool_script::Compile;
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -23,7 +29,7 @@ pub enum Operator {
 
 
 impl Operator {
-    fn get_priority(&self) -> u8 {
+    pub fn get_priority(&self) -> u8 {
         match self {
             Operator::ADD => 2,
             Operator::SUB => 2,
@@ -52,15 +58,52 @@ impl Operator {
             _ => panic!("Not supported {:?}", self)
         }
     }
+
+    /// The type of the result, none if the operator cannot be used on the types
+    pub fn result_type(&self, left: DataType, right: DataType) -> Option<DataType> {
+        let number = left.is_number() && right.is_number();
+        match self {
+            Operator::ADD | Operator::SUB | Operator::MUL | Operator::MOD if number => {
+                Some(if left == DataType::I32 && right == DataType::I32 { DataType::I32 } else { DataType::F32 })
+            }
+            Operator::DIV if number => Some(DataType::F32),
+            Operator::LT | Operator::GT | Operator::LE | Operator::GE if number => Some(DataType::Bool),
+            Operator::EQ | Operator::NEQ if number || left == right => Some(DataType::Bool),
+            _ => None
+        }
+    }
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+impl Display for Operator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Operator::ADD => "+",
+            Operator::SUB => "-",
+            Operator::MUL => "*",
+            Operator::DIV => "/",
+            Operator::MOD => "%",
+            Operator::EQ => "==",
+            Operator::NEQ => "!=",
+            Operator::GT => ">",
+            Operator::LT => "<",
+            Operator::GE => ">=",
+            Operator::LE => "<=",
+            Operator::LeftB => "(",
+            Operator::RightB => ")",
+        })
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
 pub enum ExpressionElement {
     CONST(f32),
     GAME(u8),
     DATA(u8),
     STACK(u8),
     OP(Operator),
+    /// call the function with the args on the calc stack
+    CALL(String, u8),
+    BUILTIN(Builtin),
 }
 
 
@@ -108,63 +151,185 @@ impl Compile for ExpressionElement {
             ExpressionElement::OP(op) => {
                 op.flush(binary)?;
             }
+            ExpressionElement::CALL(name, args) => {
+                binary.push(13);
+                name.as_str().flush(binary)?;
+                binary.push(*args);
+            }
+            ExpressionElement::BUILTIN(builtin) => {
+                binary.push(*builtin as u8);
+            }
         }
         Ok(())
     }
 }
 
+/// The postfix expression
 #[derive(Clone, PartialEq, Debug, Default)]
 pub struct Expression {
     tree: Vec<ExpressionElement>,
-    op: Vec<Operator>,
-    ops: usize,
+    ty: DataType,
 }
 
 impl Expression {
-    fn push_operator(&mut self, op: Operator) {
-        match op {
-            Operator::LeftB => {
-                self.op.push(op);
+    /// Lower the expression, check the types and fold the const values
+    pub fn lower(expr: &Expr, context: &Context) -> Result<Self, Diagnostic> {
+        let mut expression = Self::default();
+        expression.ty = expression.push_expr(expr, context)?;
+        Ok(expression)
+    }
+
+    /// The type of the result
+    pub fn ty(&self) -> DataType {
+        self.ty
+    }
+
+    fn push_expr(&mut self, expr: &Expr, context: &Context) -> Result<DataType, Diagnostic> {
+        Ok(match expr {
+            Expr::Number(value, _) => {
+                self.push_tree(ExpressionElement::CONST(*value));
+                if value.fract() == 0.0 && value.abs() < i32::MAX as f32 { DataType::I32 } else { DataType::F32 }
+            }
+            Expr::Bool(value, _) => {
+                self.push_tree(ExpressionElement::CONST(if *value { 1.0 } else { 0.0 }));
+                DataType::Bool
+            }
+            Expr::Str(value, span) => {
+                let idx = context.intern(value).ok_or_else(|| {
+                    Diagnostic::error("string is not allowed here").with_span(*span)
+                })?;
+                self.push_tree(ExpressionElement::CONST(idx as f32));
+                DataType::Str
             }
-            Operator::RightB => {
-                while let Some(old_op) = self.op.pop() {
-                    if old_op == Operator::LeftB {
-                        break;
+            Expr::Var(ident) => {
+                let (value, ty) = context.find_var(&ident.name).map_err(|e| e.with_span(ident.span))?;
+                self.push_tree(value);
+                ty
+            }
+            Expr::Neg(value, _) => {
+                let value = Self::lower(value, context)?;
+                expect_number(value.ty, expr.span())?;
+                if let Some(ExpressionElement::CONST(v)) = value.as_value() {
+                    self.push_tree(ExpressionElement::CONST(-v));
+                } else {
+                    //there is no neg command so use 0 - value
+                    self.push_tree(ExpressionElement::CONST(0.0));
+                    for x in value.tree {
+                        self.push_tree(x);
                     }
-                    self.ops -= 1;
-                    self.push_tree(ExpressionElement::OP(old_op));
+                    self.push_tree(ExpressionElement::OP(Operator::SUB));
                 }
+                value.ty
+            }
+            Expr::Binary(left, op, right) => {
+                let left = self.push_expr(left, context)?;
+                let right = self.push_expr(right, context)?;
+                self.push_tree(ExpressionElement::OP(*op));
+                op.result_type(left, right).ok_or_else(|| {
+                    Diagnostic::error(format!("cannot use `{}` on `{}` and `{}`", op, left, right))
+                        .with_span(expr.span())
+                })?
             }
-            _ => {
-                self.ops += 1;
-                while self.op.len() > 0 {
-                    let top_op = self.op[self.op.len() - 1];
-                    if top_op.get_priority() >= op.get_priority() {
-                        self.push_tree(ExpressionElement::OP(top_op));
-                        self.ops -= 1;
-                        self.op.pop();
-                    } else {
-                        break;
+            Expr::Call(call) => {
+                let params = context.find_function(&call.name.name).ok_or_else(|| {
+                    Diagnostic::error(format!("unknown function `{}`", call.name.name))
+                        .with_span(call.name.span)
+                })?;
+                if params != call.args.len() {
+                    return Err(Diagnostic::error(format!("function `{}` takes {} args but {} are given",
+                                                         call.name.name, params, call.args.len()))
+                        .with_span(call.span));
+                }
+                for x in &call.args {
+                    let ty = self.push_expr(x, context)?;
+                    if !ty.is_number() && ty != DataType::Bool {
+                        return Err(Diagnostic::error(format!("expected number or bool but found `{}`", ty))
+                            .with_span(x.span())
+                            .with_note("the args of the function are f32"));
                     }
                 }
-                self.op.push(op);
+                self.push_tree(ExpressionElement::CALL(call.name.name.clone(), params as u8));
+                DataType::F32
             }
+            Expr::Builtin(call) => {
+                let builtin = Builtin::try_from(call.name.name.as_str()).map_err(|_| {
+                    Diagnostic::error(format!("unknown builtin function `{}`", call.name.name))
+                        .with_span(call.name.span)
+                        .with_note("the functions in the script are called by `call <name>(<args>...)`")
+                })?;
+                if builtin.get_args_count() != call.args.len() {
+                    return Err(Diagnostic::error(format!("`{}` takes {} args but {} are given",
+                                                         builtin.name(), builtin.get_args_count(), call.args.len()))
+                        .with_span(call.span));
+                }
+                for x in &call.args {
+                    let ty = self.push_expr(x, context)?;
+                    expect_number(ty, x.span())?;
+                }
+                self.push_tree(ExpressionElement::BUILTIN(builtin));
+                builtin.ty()
+            }
+        })
+    }
+
+    pub fn elements(&self) -> &[ExpressionElement] {
+        &self.tree
+    }
+
+    /// The max count of the values in the calc stack while calculating
+    pub fn calc_depth(&self) -> usize {
+        let mut depth = 0;
+        let mut max = 0;
+        for x in &self.tree {
+            depth = match x {
+                ExpressionElement::OP(_) => depth - 1,
+                ExpressionElement::CALL(_, args) => depth + 1 - *args as usize,
+                ExpressionElement::BUILTIN(builtin) => depth + 1 - builtin.get_args_count(),
+                _ => depth + 1
+            };
+            max = max.max(depth);
+        }
+        max
+    }
+
+    /// The value if the expression needn't calculate
+    pub fn as_value(&self) -> Option<ExpressionElement> {
+        match self.tree.as_slice() {
+            [ExpressionElement::OP(_)] | [ExpressionElement::CALL(..)] | [ExpressionElement::BUILTIN(_)] => None,
+            [value] => Some(value.clone()),
+            _ => None
         }
     }
 
     fn push_tree(&mut self, value: ExpressionElement) {
-        if let ExpressionElement::OP(op) = value {
-            let len = self.tree.len();
-            if len > 1 {
-                let value1 = self.tree[len - 1];
-                let value0 = self.tree[len - 2];
-                if let (ExpressionElement::CONST(c1), ExpressionElement::CONST(c2)) = (value0, value1) {
-                    self.tree.pop();
-                    self.tree.pop();
-                    self.tree.push(ExpressionElement::CONST(op.operate(c1, c2)));
-                    return;
+        match value {
+            ExpressionElement::OP(op) => {
+                let len = self.tree.len();
+                if len > 1 {
+                    if let (ExpressionElement::CONST(c1), ExpressionElement::CONST(c2)) = (&self.tree[len - 2], &self.tree[len - 1]) {
+                        let value = op.operate(*c1, *c2);
+                        self.tree.pop();
+                        self.tree.pop();
+                        self.tree.push(ExpressionElement::CONST(value));
+                        return;
+                    }
                 }
             }
+            ExpressionElement::BUILTIN(builtin) => {
+                let count = builtin.get_args_count();
+                if self.tree.len() >= count {
+                    let start = self.tree.len() - count;
+                    let args = self.tree[start..].iter()
+                        .map(|x| if let ExpressionElement::CONST(v) = x { Some(*v) } else { None })
+                        .collect::<Option<Vec<_>>>();
+                    if let Some(value) = args.and_then(|args| builtin.operate(&args)) {
+                        self.tree.truncate(start);
+                        self.tree.push(ExpressionElement::CONST(value));
+                        return;
+                    }
+                }
+            }
+            _ => {}
         }
         self.tree.push(value);
     }
@@ -173,7 +338,7 @@ impl Expression {
 impl Compile for Expression {
     fn flush(&self, binary: &mut Vec<u8>) -> Result<(), Error> {
         for value in &self.tree {
-            if let ExpressionElement::OP(_) = value {} else {
+            if !matches!(value, ExpressionElement::OP(_) | ExpressionElement::CALL(..) | ExpressionElement::BUILTIN(_)) {
                 binary.push(3);
             }
             value.flush(binary)?
@@ -182,64 +347,27 @@ impl Compile for Expression {
     }
 }
 
-pub fn try_parse_expression(raw_str: &str, context: &Context) -> Result<Expression, Error> {
-    let s = raw_str.replace(" ", "");
-    let mut begin = 0;
-    let mut expression = Expression::default();
-
-    let mut index = 0;
-    let mut parsing_value = true;
-    while index < s.len() {
-        if index + 1 < s.len() {
-            if let Ok(op) = Operator::try_from(&s[index..index + 2]) {
-                if parsing_value && begin != index {
-                    if let Ok(value) = context.parse_value(&s[begin..index]) {
-                        expression.push_tree(value);
-                    }
-                }
-                index += 2;
-                expression.push_operator(op);
-                parsing_value = true;
-                begin = index;
-                continue;
-            }
-        }
-        if let Ok(op) = Operator::try_from(&s[index..index + 1]) {
-            if parsing_value && index == begin && op == Operator::SUB && (expression.tree.len() == expression.ops) {
-                index += 1;
-                while index < s.len() && s[index..index + 1].chars().next().unwrap().is_ascii_digit() {
-                    index += 1;
-                }
-                if begin != index {
-                    if let Ok(value) = context.parse_value(&s[begin..index]) {
-                        expression.push_tree(value);
-                    }
-                }
-                parsing_value = false;
-                continue;
-            } else {
-                if parsing_value && begin != index {
-                    if let Ok(value) = context.parse_value(&s[begin..index]) {
-                        expression.push_tree(value);
-                    }
-                }
-                expression.push_operator(op);
-                parsing_value = true;
-                begin = index + 1;
-            }
-        }
-        index += 1;
+fn expect_number(ty: DataType, span: Span) -> Result<(), Diagnostic> {
+    if ty.is_number() {
+        Ok(())
+    } else {
+        Err(Diagnostic::error(format!("expected number but found `{}`", ty)).with_span(span))
     }
+}
 
-    if parsing_value && begin != index {
-        let value = context.parse_value(&s[begin..s.len()])?;
-        expression.push_tree(value);
+/// Parse and lower the expression in one line
+pub fn try_parse_expression(raw_str: &str, context: &Context) -> Result<Expression, Diagnostic> {
+    let mut diagnostics = vec![];
+    let tokens = tokenize(raw_str, &mut diagnostics);
+    if let Some(e) = diagnostics.pop() {
+        return Err(e);
     }
-    while let Some(op) = expression.op.pop() {
-        expression.push_tree(ExpressionElement::OP(op));
+    let mut parser = AstParser::new(&tokens, &mut diagnostics);
+    let expr = parser.parse_expr()?;
+    if !matches!(parser.peek().kind, TokenKind::NewLine | TokenKind::Eof) {
+        return Err(Diagnostic::error("expected end of expression").with_span(parser.peek().span));
     }
-
-    Ok(expression)
+    Expression::lower(&expr, context)
 }
 
 impl TryFrom<&str> for Operator {
@@ -269,6 +397,7 @@ impl TryFrom<&str> for Operator {
 mod test {
     use std::collections::HashMap;
 
+    use crate::builtin::Builtin;
     use crate::context::Context;
     use crate::expression::{ExpressionElement, Operator, try_parse_expression};
 
@@ -305,6 +434,7 @@ mod test {
         assert_eq!(value.tree.pop().unwrap(), ExpressionElement::CONST(1.0));
         let value = try_parse_expression("a1 * ( b1 + a2 )", &context).unwrap();
         assert_eq!(value.tree, vec![ExpressionElement::DATA(1), ExpressionElement::STACK(1), ExpressionElement::DATA(2), ExpressionElement::OP(Operator::ADD), ExpressionElement::OP(Operator::MUL)]);
+        assert_eq!(value.calc_depth(), 3);
 
         let mut value = try_parse_expression("1 + 2 < 3", &context).unwrap();
         assert_eq!(value.tree.pop().unwrap(), ExpressionElement::CONST(0.0));
@@ -328,5 +458,15 @@ mod test {
         assert_eq!(value.tree.pop().unwrap(), ExpressionElement::CONST(-8.7));
         let mut value = try_parse_expression("3*8-2*1.5+7-4*2+1", &context).unwrap();
         assert_eq!(value.tree.pop().unwrap(), ExpressionElement::CONST(21.0));
+
+        let mut value = try_parse_expression("max(sqrt(16), -abs(-3)) + pow(2, 3) * clamp(5, 0, 1)", &context).unwrap();
+        assert_eq!(value.tree.pop().unwrap(), ExpressionElement::CONST(12.0));
+        let value = try_parse_expression("lerp(a1, 10, 0.5) + player_angle()", &context).unwrap();
+        assert_eq!(value.tree, vec![ExpressionElement::DATA(1), ExpressionElement::CONST(10.0), ExpressionElement::CONST(0.5),
+                                    ExpressionElement::BUILTIN(Builtin::Lerp), ExpressionElement::BUILTIN(Builtin::PlayerAngle),
+                                    ExpressionElement::OP(Operator::ADD)]);
+        assert_eq!(value.calc_depth(), 3);
+        assert!(try_parse_expression("sqrt(1, 2)", &context).is_err());
+        assert!(try_parse_expression("aim(1)", &context).is_err());
     }
 }
\ No newline at end of file
@@ -1,9 +1,9 @@
 use std::convert::TryFrom;
 use std::io::{Error, ErrorKind};
 
-use crate::context::Context;
-use crate::pool_script::Compile;
+use crate::data_type::DataType;
 
+/// The game values could be read by the scripts, only the positions could be written
 #[derive(Debug, Copy, Clone, PartialOrd, PartialEq)]
 #[repr(u8)]
 pub enum GameData {
@@ -13,33 +13,76 @@ pub enum GameData {
     PlayerX = 3,
     PlayerY = 4,
     PlayerZ = 5,
+    /// the ticks since the stage starts
+    Tick = 6,
+    Hp = 7,
+    /// the facing angle in degrees
+    Angle = 8,
+    /// the distance moved by `move_up` in the last tick
+    Speed = 9,
     CircleCollide = 10,
+    /// the ticks since the object is summoned
+    Age = 11,
+    Difficulty = 12,
+    EnemyCount = 13,
+    BulletCount = 14,
+    PlayerLives = 15,
+    PlayerBombs = 16,
+    PlayerPower = 17,
+    PlayerFocused = 18,
+    /// the bullets grazed by the player in the stage
+    PlayerGraze = 19,
 }
 
 impl GameData {
-    pub fn get_args(&self, args: &[&str], context: &Context, binary: &mut Vec<u8>) -> Result<usize, Error> {
+    pub fn get_args_count(&self) -> usize {
         match self {
-            Self::CircleCollide => {
-                if args.len() < 1 {
-                    eprintln!("There is no more args {:?}", self);
-                    Err(Error::new(ErrorKind::InvalidData, "[parse states.game data]args is not enough"))
-                } else {
-                    binary.push(*self as u8);
-                    context.parse_value(args[0])?.flush(binary)?;
-                    Ok(1)
-                }
-            }
-            _ => {
-                eprintln!("There is no args about {:?}", self);
-                Err(Error::new(ErrorKind::InvalidData, "[parse states.game data]get args failed"))
-            }
+            GameData::CircleCollide => 1,
+            _ => panic!("no such arg")
         }
     }
 
-    pub fn get_args_count(&self) -> usize {
+    pub fn name(&self) -> &'static str {
         match self {
-            GameData::CircleCollide => 1,
-            _ => panic!("no such arg")
+            GameData::PosX => "pos_x",
+            GameData::PosY => "pos_y",
+            GameData::PosZ => "pos_z",
+            GameData::PlayerX => "player_x",
+            GameData::PlayerY => "player_y",
+            GameData::PlayerZ => "player_z",
+            GameData::Tick => "stage_tick",
+            GameData::Hp => "self_hp",
+            GameData::Angle => "self_angle",
+            GameData::Speed => "self_speed",
+            GameData::CircleCollide => "circle",
+            GameData::Age => "self_age",
+            GameData::Difficulty => "difficulty",
+            GameData::EnemyCount => "enemy_count",
+            GameData::BulletCount => "bullet_count",
+            GameData::PlayerLives => "player_lives",
+            GameData::PlayerBombs => "player_bombs",
+            GameData::PlayerPower => "player_power",
+            GameData::PlayerFocused => "player_focused",
+            GameData::PlayerGraze => "player_graze",
+        }
+    }
+
+    /// The value could be used as a var, the collide type is not
+    pub fn is_var(&self) -> bool {
+        *self != GameData::CircleCollide
+    }
+
+    /// Only the positions could be stored
+    pub fn is_writable(&self) -> bool {
+        *self <= GameData::PlayerZ
+    }
+
+    pub fn ty(&self) -> DataType {
+        match self {
+            GameData::Tick | GameData::Age | GameData::Difficulty | GameData::EnemyCount | GameData::BulletCount
+            | GameData::PlayerLives | GameData::PlayerBombs | GameData::PlayerGraze => DataType::I32,
+            GameData::PlayerFocused => DataType::Bool,
+            _ => DataType::F32,
         }
     }
 }
@@ -49,7 +92,26 @@ impl TryFrom<u8> for GameData {
 
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
+            0 => Ok(GameData::PosX),
+            1 => Ok(GameData::PosY),
+            2 => Ok(GameData::PosZ),
+            3 => Ok(GameData::PlayerX),
+            4 => Ok(GameData::PlayerY),
+            5 => Ok(GameData::PlayerZ),
+            6 => Ok(GameData::Tick),
+            7 => Ok(GameData::Hp),
+            8 => Ok(GameData::Angle),
+            9 => Ok(GameData::Speed),
             10 => Ok(GameData::CircleCollide),
+            11 => Ok(GameData::Age),
+            12 => Ok(GameData::Difficulty),
+            13 => Ok(GameData::EnemyCount),
+            14 => Ok(GameData::BulletCount),
+            15 => Ok(GameData::PlayerLives),
+            16 => Ok(GameData::PlayerBombs),
+            17 => Ok(GameData::PlayerPower),
+            18 => Ok(GameData::PlayerFocused),
+            19 => Ok(GameData::PlayerGraze),
             _ => {
                 eprintln!("There is unknown binary value {}", value);
                 Err(Error::new(ErrorKind::InvalidData, "[parse states.game data]no such states.game value"))
@@ -69,8 +131,74 @@ impl TryFrom<&str> for GameData {
             "player_x" => Ok(GameData::PlayerX),
             "player_y" => Ok(GameData::PlayerY),
             "player_z" => Ok(GameData::PlayerZ),
+            "stage_tick" => Ok(GameData::Tick),
+            "self_hp" => Ok(GameData::Hp),
+            "self_angle" => Ok(GameData::Angle),
+            "self_speed" => Ok(GameData::Speed),
             "circle" => Ok(GameData::CircleCollide),
+            "self_age" => Ok(GameData::Age),
+            "difficulty" => Ok(GameData::Difficulty),
+            "enemy_count" => Ok(GameData::EnemyCount),
+            "bullet_count" => Ok(GameData::BulletCount),
+            "player_lives" => Ok(GameData::PlayerLives),
+            "player_bombs" => Ok(GameData::PlayerBombs),
+            "player_power" => Ok(GameData::PlayerPower),
+            "player_focused" => Ok(GameData::PlayerFocused),
+            "player_graze" => Ok(GameData::PlayerGraze),
             _ => Err(Error::new(ErrorKind::InvalidData, "[parse states.game data]expected states.game data but found : ".to_owned() + value))
         }
     }
+}
+
+/// The items dropped by `drop_item`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ItemKind {
+    Power = 0,
+    Point = 1,
+    Bomb = 2,
+    Life = 3,
+    FullPower = 4,
+}
+
+impl ItemKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ItemKind::Power => "power",
+            ItemKind::Point => "point",
+            ItemKind::Bomb => "bomb",
+            ItemKind::Life => "life",
+            ItemKind::FullPower => "full_power",
+        }
+    }
+}
+
+impl TryFrom<u8> for ItemKind {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ItemKind::Power),
+            1 => Ok(ItemKind::Point),
+            2 => Ok(ItemKind::Bomb),
+            3 => Ok(ItemKind::Life),
+            4 => Ok(ItemKind::FullPower),
+            _ => Err(Error::new(ErrorKind::InvalidData, format!("[parse item]no such item {}", value)))
+        }
+    }
+}
+
+impl TryFrom<&str> for ItemKind {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "power" => Ok(ItemKind::Power),
+            "point" => Ok(ItemKind::Point),
+            "bomb" => Ok(ItemKind::Bomb),
+            "life" => Ok(ItemKind::Life),
+            "full_power" => Ok(ItemKind::FullPower),
+            _ => Err(Error::new(ErrorKind::InvalidData, format!("[parse item]no such item {}", value)))
+        }
+    }
 }
\ No newline at end of file
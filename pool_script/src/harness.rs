@@ -0,0 +1,198 @@
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use crate::game_data::GameData;
+use crate::manager::{DEFAULT_BUDGET, ScriptManager};
+use crate::random::Random;
+use crate::vm::{ScriptCommand, ScriptContext, ScriptHost};
+
+/// How the script is run without the game
+#[derive(Debug, Clone)]
+pub struct RunConfig {
+    pub ticks: u32,
+    /// the player does not move
+    pub player: (f32, f32),
+    /// the args the object is summoned with
+    pub args: Vec<f32>,
+    pub seed: u64,
+    /// the commands could run in one tick
+    pub budget: u32,
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        Self {
+            ticks: 60,
+            player: (0.0, -100.0),
+            args: vec![],
+            seed: 0,
+            budget: DEFAULT_BUDGET,
+        }
+    }
+}
+
+impl RunConfig {
+    /// Parse the settings like `ticks=60 args=1,2 player=0,-100 seed=1 budget=1000`, the missing ones are default
+    pub fn parse<'a>(settings: impl IntoIterator<Item=&'a str>) -> Result<Self, Error> {
+        let mut config = Self::default();
+        for setting in settings {
+            let error = || Error::new(ErrorKind::InvalidInput, format!("[run]bad setting {}", setting));
+            let (key, value) = setting.split_once('=').ok_or_else(error)?;
+            let numbers = value.split(',').filter(|x| !x.is_empty())
+                .map(|x| x.trim().parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| error())?;
+            match (key, numbers.as_slice()) {
+                ("ticks", [ticks]) => config.ticks = *ticks as u32,
+                ("seed", [seed]) => config.seed = *seed as u64,
+                ("budget", [budget]) => config.budget = *budget as u32,
+                ("player", [x, y]) => config.player = (*x, *y),
+                ("args", _) => config.args = numbers,
+                _ => return Err(error())
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Run the script of one object at the origin and return what it does, one line for each command like `0001 Move(1.0)`
+///
+/// `start` runs at tick 0 if the script has it, then tick runs until the object is killed.
+/// The position is printed when the script stores to it. The summoned objects are printed but not run.
+/// The runtime error is printed like `0001 error ...` and kills the object.
+pub fn run(dir: &Path, name: &str, config: &RunConfig) -> Result<Vec<String>, Error> {
+    //the summons need the data count of the scripts they use
+    let mut script_manager = ScriptManager::with_dir(dir.to_path_buf());
+    script_manager.load_scripts();
+    script_manager.budget = config.budget;
+    let desc = script_manager.get_script(name)
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("[run]cannot load script {}", name)))?;
+    let mut context = ScriptContext::new(desc, config.args.clone());
+    let mut host = RunHost {
+        player: [config.player.0, config.player.1, 0.0],
+        random: Random::new(config.seed),
+        ..Default::default()
+    };
+    let mut lines = vec![];
+    if desc.functions.contains_key("start") {
+        context.exe_fn_if_present("start", &[], &mut host, &script_manager);
+        if host.record(0, &mut lines) {
+            return Ok(lines);
+        }
+    }
+    if desc.tick_function.is_none() {
+        return Ok(lines);
+    }
+    for tick in 1..=config.ticks {
+        host.tick = tick;
+        host.age = tick;
+        let last = host.pos;
+        context.tick_function(&mut host, &script_manager, None);
+        if host.pos != last {
+            lines.push(format!("{:04} pos {} {} {}", tick, host.pos[0], host.pos[1], host.pos[2]));
+        }
+        if host.record(tick, &mut lines) {
+            break;
+        }
+    }
+    Ok(lines)
+}
+
+/// The object run without the game, the game values not here are 0
+#[derive(Debug, Default)]
+struct RunHost {
+    pos: [f32; 3],
+    player: [f32; 3],
+    tick: u32,
+    age: u32,
+    random: Random,
+    commands: Vec<ScriptCommand>,
+    errors: Vec<String>,
+}
+
+impl RunHost {
+    /// Return true if the object is killed
+    fn record(&mut self, tick: u32, lines: &mut Vec<String>) -> bool {
+        let mut killed = false;
+        for error in self.errors.drain(..) {
+            lines.push(format!("{:04} error {}", tick, error));
+        }
+        for command in self.commands.drain(..) {
+            killed |= matches!(command, ScriptCommand::Kill);
+            lines.push(format!("{:04} {:?}", tick, command));
+        }
+        killed
+    }
+}
+
+impl ScriptHost for RunHost {
+    fn get(&self, data: GameData) -> f32 {
+        match data {
+            GameData::PosX | GameData::PosY | GameData::PosZ => self.pos[data as usize],
+            GameData::PlayerX | GameData::PlayerY | GameData::PlayerZ => self.player[data as usize - 3],
+            GameData::Tick => self.tick as f32,
+            GameData::Age => self.age as f32,
+            _ => 0.0,
+        }
+    }
+
+    fn set(&mut self, data: GameData, value: f32) {
+        match data {
+            GameData::PosX | GameData::PosY | GameData::PosZ => self.pos[data as usize] = value,
+            GameData::PlayerX | GameData::PlayerY | GameData::PlayerZ => self.player[data as usize - 3] = value,
+            _ => unreachable!("{} is read only", data.name())
+        }
+    }
+
+    fn submit(&mut self, command: ScriptCommand) {
+        self.commands.push(command);
+    }
+
+    fn random(&mut self) -> &mut Random {
+        &mut self.random
+    }
+
+    fn error(&mut self, error: Error) {
+        self.errors.push(error.to_string());
+        self.submit(ScriptCommand::Kill);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use crate::harness::{run, RunConfig};
+
+    /// Run each script or source in `pooltouhou/res/script` and compare to `res/script/golden/<name>.txt`, set `PTH_BLESS` to write the outputs
+    ///
+    /// The first line of the golden file is the settings of the run like `# ticks=60 args=1,1`.
+    #[test]
+    fn golden() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../pooltouhou/res/script");
+        let mut names = dir.read_dir().unwrap()
+            .filter_map(|x| {
+                let file_name = x.ok()?.file_name().into_string().ok()?;
+                file_name.strip_suffix(".pthpsb").or_else(|| file_name.strip_suffix(".pthps")).map(str::to_owned)
+            })
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        assert!(!names.is_empty());
+        for name in names {
+            let golden = dir.join("golden").join(name.clone() + ".txt");
+            let expected = std::fs::read_to_string(&golden).unwrap_or_default();
+            let header = expected.lines().next().filter(|x| x.starts_with("# ")).unwrap_or("# ticks=60 args=1,1,1,1");
+            let config = RunConfig::parse(header[2..].split_whitespace()).unwrap();
+            let output = std::iter::once(header.to_owned()).chain(run(&dir, &name, &config).unwrap())
+                .map(|x| x + "\n")
+                .collect::<String>();
+            if std::env::var_os("PTH_BLESS").is_some() {
+                std::fs::create_dir_all(golden.parent().unwrap()).unwrap();
+                std::fs::write(&golden, output).unwrap();
+            } else {
+                assert_eq!(output, expected, "the output of {} changed", name);
+            }
+        }
+    }
+}
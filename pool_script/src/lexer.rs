@@ -0,0 +1,179 @@
+use std::str::FromStr;
+
+use crate::diagnostic::{Diagnostic, Span};
+use crate::expression::Operator;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Ident(String),
+    Number(f32),
+    /// the string in `"`
+    Str(String),
+    Op(Operator),
+    Comma,
+    Assign,
+    NewLine,
+    Eof,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+fn is_ident_start(c: char) -> bool {
+    c == '_' || c.is_alphabetic()
+}
+
+/// The field of vec2 is a part of the name like `pos.x`
+fn is_ident_continue(c: char) -> bool {
+    c == '_' || c == '.' || c.is_alphanumeric()
+}
+
+/// Split the source into tokens, the last token is always [`TokenKind::Eof`]
+///
+/// Comments are dropped and the unknown chars are reported and skipped.
+pub fn tokenize(source: &str, diagnostics: &mut Vec<Diagnostic>) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut line_no = 0;
+    for (idx, line) in source.split('\n').enumerate() {
+        line_no = idx + 1;
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let mut chars = line.char_indices().peekable();
+        while let Some((column, c)) = chars.next() {
+            let span = |end: usize| Span {
+                line: line_no,
+                column,
+                len: end - column,
+            };
+            let kind = match c {
+                ' ' | '\t' => continue,
+                '/' if line[column + 1..].starts_with('/') => break,
+                ',' => TokenKind::Comma,
+                '=' | '!' | '<' | '>' if line[column + 1..].starts_with('=') => {
+                    chars.next();
+                    tokens.push(Token {
+                        kind: TokenKind::Op(Operator::try_from(&line[column..column + 2]).unwrap()),
+                        span: span(column + 2),
+                    });
+                    continue;
+                }
+                '=' => TokenKind::Assign,
+                '"' => {
+                    match line[column + 1..].find('"') {
+                        Some(len) => {
+                            let end = column + len + 2;
+                            while chars.peek().map(|(idx, _)| *idx < end).unwrap_or(false) {
+                                chars.next();
+                            }
+                            tokens.push(Token {
+                                kind: TokenKind::Str(line[column + 1..end - 1].to_string()),
+                                span: span(end),
+                            });
+                        }
+                        None => {
+                            diagnostics.push(Diagnostic::error("the string is not end")
+                                .with_span(span(line.len()))
+                                .with_note("add `\"` at the end of the string"));
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                '+' | '-' | '*' | '/' | '%' | '(' | ')' | '<' | '>' => {
+                    TokenKind::Op(Operator::try_from(&line[column..column + 1]).unwrap())
+                }
+                '0'..='9' | '.' => {
+                    let mut end = column + 1;
+                    while let Some((idx, c)) = chars.peek() {
+                        if c.is_ascii_digit() || *c == '.' {
+                            end = *idx + 1;
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    match f32::from_str(&line[column..end]) {
+                        Ok(value) => tokens.push(Token {
+                            kind: TokenKind::Number(value),
+                            span: span(end),
+                        }),
+                        Err(_) => diagnostics.push(Diagnostic::error(format!("invalid number `{}`", &line[column..end]))
+                            .with_span(span(end)))
+                    }
+                    continue;
+                }
+                c if is_ident_start(c) => {
+                    let mut end = column + c.len_utf8();
+                    while let Some((idx, c)) = chars.peek() {
+                        if is_ident_continue(*c) {
+                            end = *idx + c.len_utf8();
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    tokens.push(Token {
+                        kind: TokenKind::Ident(line[column..end].to_string()),
+                        span: span(end),
+                    });
+                    continue;
+                }
+                _ => {
+                    diagnostics.push(Diagnostic::error(format!("unexpected char `{}`", c))
+                        .with_span(span(column + c.len_utf8())));
+                    continue;
+                }
+            };
+            tokens.push(Token {
+                kind,
+                span: span(column + c.len_utf8()),
+            });
+        }
+        tokens.push(Token {
+            kind: TokenKind::NewLine,
+            span: Span {
+                line: line_no,
+                column: line.len(),
+                len: 0,
+            },
+        });
+    }
+    tokens.push(Token {
+        kind: TokenKind::Eof,
+        span: Span {
+            line: line_no,
+            column: 0,
+            len: 0,
+        },
+    });
+    tokens
+}
+
+#[cfg(test)]
+mod test {
+    use crate::expression::Operator;
+    use crate::lexer::{tokenize, TokenKind};
+
+    #[test]
+    fn tokenize_line() {
+        let mut diagnostics = vec![];
+        let tokens = tokenize("summon_e  暗夜 -1.5 a>=b.x \"红 a\" // comment\n", &mut diagnostics)
+            .into_iter().map(|x| x.kind).collect::<Vec<_>>();
+        assert!(diagnostics.is_empty());
+        assert_eq!(tokens, vec![
+            TokenKind::Ident("summon_e".into()),
+            TokenKind::Ident("暗夜".into()),
+            TokenKind::Op(Operator::SUB),
+            TokenKind::Number(1.5),
+            TokenKind::Ident("a".into()),
+            TokenKind::Op(Operator::GE),
+            TokenKind::Ident("b.x".into()),
+            TokenKind::Str("红 a".into()),
+            TokenKind::NewLine,
+            TokenKind::NewLine,
+            TokenKind::Eof,
+        ]);
+    }
+}
@@ -2,8 +2,23 @@ pub use pool_script::*;
 pub use pool_script::PoolScriptBin;
 
 pub mod game_data;
+pub mod builtin;
+pub mod data_type;
+pub mod random;
 
 pub mod context;
 pub mod expression;
+pub mod decompile;
+pub mod disasm;
+pub mod ast;
+pub mod lexer;
+pub mod diagnostic;
+pub mod verify;
+pub mod container;
+
+pub mod vm;
+pub mod manager;
+pub mod debugger;
+pub mod harness;
 
 pub mod pool_script;
@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use crate::container::{self, crc32};
+use crate::data_type::DataType;
+use crate::pool_script::{FunctionDesc, Parser, PoolScriptBin};
+use crate::verify::verify;
+
+#[derive(Debug, Clone)]
+pub struct ScriptDesc {
+    pub name: String,
+    pub version: u32,
+    data_count: u8,
+    pub data_types: Vec<DataType>,
+    /// the strings referred by the str values
+    pub strings: Vec<String>,
+    /// the index of each data slot by the name
+    pub data_index: HashMap<String, u8>,
+    pub(crate) index: usize,
+    pub functions: HashMap<String, FunctionDesc>,
+    pub tick_function: Option<FunctionDesc>,
+}
+
+/// The commands a function could run each time the game calls it by default
+pub const DEFAULT_BUDGET: u32 = 100_000;
+
+#[derive(Debug)]
+pub struct ScriptManager {
+    pub scripts: Vec<ScriptDesc>,
+    pub script_map: HashMap<String, usize>,
+    /// the dir to load the scripts, [`script_dir`] if none
+    dir: Option<PathBuf>,
+    /// the commands could run in one tick of an object (or a callback), the functions called share it
+    pub budget: u32,
+}
+
+impl Default for ScriptManager {
+    fn default() -> Self {
+        Self {
+            scripts: vec![],
+            script_map: HashMap::new(),
+            dir: None,
+            budget: DEFAULT_BUDGET,
+        }
+    }
+}
+
+impl ScriptManager {
+    pub fn with_dir(dir: PathBuf) -> Self {
+        Self {
+            dir: Some(dir),
+            ..Default::default()
+        }
+    }
+
+    fn dir(&self) -> PathBuf {
+        self.dir.clone().unwrap_or_else(script_dir)
+    }
+
+    pub fn get_script_data_count(&self, name: &str) -> u8 {
+        if let Some(index) = self.script_map.get(name) {
+            self.scripts[*index].data_count
+        } else {
+            panic!("There is no script with name:  {}", name)
+        }
+    }
+
+    pub fn load_script_data_count(&mut self, name: &str) -> u8 {
+        if let Some(index) = self.script_map.get(name) {
+            self.scripts[*index].data_count
+        } else if let Some(script) = self.load_script(name) {
+            script.data_count
+        } else {
+            panic!("There is no script with name:  {}", name)
+        }
+    }
+
+    pub fn get_script(&self, name: &str) -> Option<&ScriptDesc> {
+        if let Some(index) = self.script_map.get(name) {
+            self.scripts.get(*index)
+        } else {
+            None
+        }
+    }
+
+    pub fn load_script(&mut self, name: &str) -> Option<&ScriptDesc> {
+        log::debug!("loading script: {}", name);
+        let source = self.dir().join(name.to_owned() + ".pthps");
+        //the source is preferred so the game could run from it while developing
+        let bin = if source.is_file() {
+            load_source(&source)?
+        } else {
+            let path = self.dir().join(name.to_owned() + ".pthpsb");
+            let file = match File::open(&path) {
+                Ok(file) => file,
+                Err(_) => {
+                    eprintln!("Script not found in {:?}", path);
+                    return None;
+                }
+            };
+            match PoolScriptBin::try_parse_bin(BufReader::new(file)) {
+                Ok(bin) => bin,
+                Err(e) => {
+                    log::error!("Load script {} failed: {}", name, e);
+                    return None;
+                }
+            }
+        };
+        let index = self.insert(name, bin);
+        self.scripts.get(index)
+    }
+
+    /// Add the script not loaded from the dir, return its index
+    pub fn insert(&mut self, name: &str, bin: PoolScriptBin) -> usize {
+        let index = self.scripts.len();
+        self.scripts.push(ScriptDesc::new(name, bin, index));
+        self.script_map.insert(name.into(), index);
+        index
+    }
+
+    /// Compile the source and replace the script with the same name, the index is kept
+    /// so the running contexts still refer to it.
+    ///
+    /// Return the index and the replaced script, none if the source has errors.
+    pub fn reload_source(&mut self, path: &Path) -> Option<(usize, Option<ScriptDesc>)> {
+        let name = path.file_stem()?.to_string_lossy().into_owned();
+        let bin = load_source(path)?;
+        if let Some(&index) = self.script_map.get(&name) {
+            let script = ScriptDesc::new(&name, bin, index);
+            Some((index, Some(std::mem::replace(&mut self.scripts[index], script))))
+        } else {
+            Some((self.insert(&name, bin), None))
+        }
+    }
+
+    pub fn load_scripts(&mut self) {
+        self.scripts.clear();
+        self.script_map.clear();
+        let path = self.dir();
+        let dir = path.read_dir().unwrap();
+        for file in dir {
+            match file {
+                Ok(entry) => {
+                    if let Ok(file_type) = entry.file_type() {
+                        let file_name = entry.file_name().into_string().unwrap();
+                        let name = file_name.strip_suffix(".pthpsb").or_else(|| file_name.strip_suffix(".pthps"));
+                        if let (true, Some(name)) = (file_type.is_file(), name) {
+                            if !self.script_map.contains_key(name) {
+                                self.load_script(name);
+                            }
+                        }
+                    }
+                }
+                Err(err) => {
+                    eprintln!("read entry failed! {}", err);
+                }
+            }
+        }
+    }
+}
+
+impl ScriptDesc {
+    fn new(name: &str, mut bin: PoolScriptBin, index: usize) -> Self {
+        let tick_function = bin.functions.remove("tick");
+        let script = Self {
+            name: name.into(),
+            version: bin.version,
+            index,
+            functions: bin.functions,
+            tick_function,
+            data_count: bin.data.len() as _,
+            data_types: bin.data_types,
+            strings: bin.strings,
+            data_index: bin.data,
+        };
+        log::info!("Loaded script {} with data count {} and tick max stack {}", name,
+            script.data_count, script.tick_function.as_ref().map(|x| x.max_stack).unwrap_or(u16::MAX));
+        script
+    }
+}
+
+/// The dir of the scripts and their sources
+pub fn script_dir() -> PathBuf {
+    std::env::current_dir().unwrap().join("script")
+}
+
+/// Compile the source or load the binary cached by the last compile of the same source
+///
+/// The cache `<name>.pthpsc` is the [`cache_key`] of the source (4B) and the binary.
+pub fn load_source(path: &Path) -> Option<PoolScriptBin> {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            log::error!("Read script source {:?} failed: {}", path, e);
+            return None;
+        }
+    };
+    let hash = cache_key(&source).to_be_bytes();
+    let cache_path = path.with_extension("pthpsc");
+    if let Ok(cache) = std::fs::read(&cache_path) {
+        if cache.len() > hash.len() && cache[..hash.len()] == hash {
+            match PoolScriptBin::try_parse_bin(BufReader::new(&cache[hash.len()..])) {
+                Ok(bin) => return Some(bin),
+                Err(e) => log::warn!("Load script cache {:?} failed: {}", cache_path, e)
+            }
+        }
+    }
+    let bin = compile_source(&path.file_name().unwrap_or_default().to_string_lossy(), &source)?;
+    let cache = container::write(&bin).map(|bin| [&hash[..], &bin].concat());
+    if let Err(e) = cache.and_then(|cache| std::fs::write(&cache_path, cache)) {
+        log::warn!("Save script cache {:?} failed: {}", cache_path, e);
+    }
+    Some(bin)
+}
+
+/// The CRC-32 of the compiler version, the container version and the source,
+/// so the caches of an older compiler are compiled again
+fn cache_key(source: &str) -> u32 {
+    let key = [env!("CARGO_PKG_VERSION").as_bytes(), &container::VERSION.to_be_bytes(), source.as_bytes()].concat();
+    crc32(&key)
+}
+
+/// Compile and verify the source, the diagnostics are logged
+pub fn compile_source(file_name: &str, source: &str) -> Option<PoolScriptBin> {
+    let mut parser = Parser::new(source.as_bytes());
+    let result = parser.try_parse();
+    let diagnostics = match &result {
+        Ok(_) => parser.diagnostics(),
+        Err(diagnostics) => diagnostics.as_slice()
+    };
+    for diagnostic in diagnostics {
+        if diagnostic.is_error() {
+            log::error!("{}", diagnostic.render(file_name, source));
+        } else {
+            log::warn!("{}", diagnostic.render(file_name, source));
+        }
+    }
+    let bin = match result {
+        Ok(bin) => bin,
+        Err(diagnostics) => {
+            log::error!("Compile script {} failed with {} error(s)", file_name, diagnostics.iter().filter(|x| x.is_error()).count());
+            return None;
+        }
+    };
+    if let Err(e) = verify(&bin) {
+        log::error!("Verify script {} failed: {}", file_name, e);
+        return None;
+    }
+    Some(bin)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::container::{self, crc32};
+    use crate::manager::{compile_source, load_source};
+
+    #[test]
+    fn skip_cache_of_other_compiler() {
+        let dir = std::env::temp_dir().join(format!("pool_script_cache_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cached.pthps");
+        let source = "function tick\n    move_up 1\nend\n";
+        std::fs::write(&path, source).unwrap();
+        //a cache keyed by the source only, with the binary of another script
+        let stale = compile_source("other", "function tick\n    kill\nend\n").unwrap();
+        let cache = [&crc32(source.as_bytes()).to_be_bytes()[..], &container::write(&stale).unwrap()].concat();
+        std::fs::write(path.with_extension("pthpsc"), cache).unwrap();
+
+        //move_up, not kill
+        assert_eq!(10, load_source(&path).unwrap().functions["tick"].code[0]);
+        //the cache is written again with the new key
+        assert_eq!(10, load_source(&path).unwrap().functions["tick"].code[0]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
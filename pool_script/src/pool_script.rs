@@ -1,27 +1,42 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
 use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Write};
 
+use crate::ast::{AstParser, Collide, Expr, Function, Ident, Name, Script, Stmt};
+use crate::container;
 use crate::context::Context;
-use crate::expression::{ExpressionElement, try_parse_expression};
+use crate::data_type::DataType;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::expression::{Expression, ExpressionElement};
 use crate::game_data::GameData;
+use crate::lexer::tokenize;
+use crate::verify::{CALC_STACK_SIZE, verify};
 
 pub trait Compile {
     fn flush(&self, binary: &mut Vec<u8>) -> Result<(), Error>;
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Loop {
     Start(usize),
     End(usize),
 }
 
+/// The function could run in the parallel pass
+pub const FLAG_THREAD_SAFE: u8 = 1;
+
+/// Written as the length of the name in the summon commands, the pointer to the str value follows it
+pub const DYNAMIC_NAME: u16 = u16::MAX;
+
 #[derive(Debug, Clone)]
 pub struct FunctionDesc {
     pub code: Vec<u8>,
     pub loops: Vec<Loop>,
     pub max_stack: u16,
+    /// the max count of the values pushed to the calc stack
+    pub max_calc: u8,
     pub thread_safe: bool,
 }
 
@@ -29,204 +44,133 @@ pub struct FunctionDesc {
 pub struct PoolScriptBin {
     pub version: u32,
     pub data: HashMap<String, u8>,
+    /// the type of each data slot, both slots of vec2 are vec2
+    pub data_types: Vec<DataType>,
+    /// the strings used by the script, the str value is the index
+    pub strings: Vec<String>,
     pub functions: HashMap<String, FunctionDesc>,
 }
 
-pub struct Parser {
-    reader: BufReader<File>,
-    line: usize,
+pub struct Parser<R: Read = File> {
+    reader: R,
+    diagnostics: Vec<Diagnostic>,
 }
 
-impl Parser {
-    pub fn new(file: std::fs::File) -> Self {
-        let reader = std::io::BufReader::new(file);
+impl<R: Read> Parser<R> {
+    pub fn new(file: R) -> Self {
         Self {
-            reader,
-            line: 0,
+            reader: file,
+            diagnostics: vec![],
         }
     }
 
-    pub fn read_line(&mut self, buf: &mut String) -> std::io::Result<usize> {
-        self.line += 1;
-        self.reader.read_line(buf).map_err(|e| {
-            eprintln!("Read line {} failed", self.line);
-            e
-        })
+    /// The diagnostics reported while parsing
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
     }
 
-    pub fn try_parse(&mut self) -> Result<PoolScriptBin, Error> {
-        PoolScriptBin::try_parse(self).map_err(|e| {
-            eprintln!("In line {}", self.line);
-            e
-        })
+    /// Parse the whole script and return all the diagnostics if there is any error
+    pub fn try_parse(&mut self) -> Result<PoolScriptBin, Vec<Diagnostic>> {
+        let mut source = String::new();
+        if let Err(e) = self.reader.read_to_string(&mut source) {
+            return Err(vec![Diagnostic::error(format!("read source failed: {}", e))]);
+        }
+        let tokens = tokenize(&source, &mut self.diagnostics);
+        let script = AstParser::new(&tokens, &mut self.diagnostics).parse_script();
+        let bin = PoolScriptBin::compile(&script, &mut self.diagnostics);
+        self.diagnostics.sort_by_key(|x| x.span.line);
+        if self.diagnostics.iter().any(Diagnostic::is_error) {
+            Err(std::mem::take(&mut self.diagnostics))
+        } else {
+            Ok(bin)
+        }
     }
 }
 
 impl PoolScriptBin {
-    fn try_parse(mut parser: &mut Parser) -> Result<Self, Error> {
+    /// Lower the script to the binary, the errors are pushed into diagnostics
+    pub fn compile(script: &Script, diagnostics: &mut Vec<Diagnostic>) -> Self {
         let mut data = HashMap::new();
-        let mut functions = HashMap::new();
-        loop {
-            let mut line = String::new();
-            let size = parser.read_line(&mut line)
-                .unwrap();
-            let line = line.trim();
-            if line.starts_with("data") {
-                parse_data(&mut parser, &mut data)?;
-            }
-            let mut context = Context::new(&data);
-            if line.starts_with("function") {
-                let function_with_name: Vec<&str> = line.split(" ").collect();
-                if function_with_name.len() < 2 {
-                    return Err(Error::new(ErrorKind::InvalidData, "[parse function]where is the function name"));
+        let mut data_types = vec![];
+        for decl in &script.data {
+            let ty = match DataType::try_from(decl.ty.name.as_str()) {
+                Ok(ty) => ty,
+                Err(_) => {
+                    diagnostics.push(unknown_type(&decl.ty));
+                    continue;
+                }
+            };
+            let slots = ty.slot_names(&decl.name.name);
+            if data.contains_key(&decl.name.name) || slots.iter().any(|x| data.contains_key(x)) {
+                diagnostics.push(Diagnostic::error(format!("data `{}` is declared twice", decl.name.name))
+                    .with_span(decl.name.span));
+            } else if data_types.len() + slots.len() > u8::MAX as usize {
+                diagnostics.push(Diagnostic::error("too many data")
+                    .with_span(decl.name.span)
+                    .with_note(format!("the script could have at most {} data slots", u8::MAX)));
+            } else {
+                for slot in slots {
+                    data.insert(slot, data_types.len() as u8);
+                    data_types.push(ty);
                 }
-                let result = parse_function(function_with_name[1], &mut parser, &mut context)?;
-                functions.insert(function_with_name[1].to_string(), result);
             }
-            if size == 0 {
-                break;
+        }
+        let strings = RefCell::new(vec![]);
+        //tick is not in the functions at runtime so it cannot be called
+        let params = script.functions.iter()
+            .filter(|x| x.name.name != "tick")
+            .map(|x| (x.name.name.clone(), x.params.len()))
+            .collect::<HashMap<_, _>>();
+        let mut functions = HashMap::new();
+        let mut calls = HashMap::new();
+        for function in &script.functions {
+            if functions.contains_key(&function.name.name) {
+                diagnostics.push(Diagnostic::error(format!("function `{}` is defined twice", function.name.name))
+                    .with_span(function.name.span));
             }
+            let mut context = Context::new(&data)
+                .with_data_types(&data_types)
+                .with_functions(&params)
+                .with_strings(&strings);
+            let (desc, called) = compile_function(function, &mut context, diagnostics);
+            functions.insert(function.name.name.clone(), desc);
+            calls.insert(function.name.name.clone(), called);
         }
-        Ok(Self {
-            version: 0,
+        propagate_thread_safe(&mut functions, &calls);
+        Self {
+            version: 1,
             data,
+            data_types,
+            strings: strings.into_inner(),
             functions,
-        })
+        }
+    }
+
+    /// Load the binary and verify it so the runtime could execute it without checking
+    ///
+    /// Both the v1 container and the old version 0 files are accepted.
+    pub fn try_parse_bin<R: Read>(mut reader: BufReader<R>) -> Result<Self, Error> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        let bin = if bytes.starts_with(container::MAGIC) {
+            container::read(&bytes)?
+        } else {
+            Self::parse_v0(&bytes)?
+        };
+        verify(&bin)?;
+        Ok(bin)
     }
 
-    pub fn try_parse_bin(mut reader: BufReader<File>) -> Result<Self, Error> {
-        let mut buf = [0; 16];
-        let size = reader.read(&mut buf[0..5]).expect("read file failed");
-        if size < 5 {
+    fn parse_v0(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 5 {
             return Err(Error::new(std::io::ErrorKind::InvalidData, "No const file data"));
         }
-        let version = u32::from_be_bytes(buf[0..4].try_into().unwrap());
-        let data_count = buf[4];
-        let mut functions = HashMap::new();
-        loop {
-            let mut binary = Vec::with_capacity(128);
-            let mut max_stack_idx = -1i32;
-            let mut loop_vec = Vec::new();
-            let function_name = read_str(&mut reader, &mut binary, false);
-            if function_name.is_empty() {
-                break;
-            }
-            log::info!("Loading script function '{}'", function_name);
-            binary.clear();
-
-            let mut loops = 0;
-            loop {
-                let read = reader.read(&mut buf[0..1]).unwrap();
-                if read == 0 {
-                    break;
-                }
-                binary.push(buf[0]);
-                match buf[0] {
-                    0 => {
-                        if loops > 0 {
-                            log::debug!("end loop");
-                            loops -= 1;
-                            loop_vec.push(Loop::End(binary.len()));
-                        } else {
-                            log::debug!("return");
-                            break;
-                        }
-                    }
-                    1 => {
-                        log::debug!("loop");
-                        loop_vec.push(Loop::Start(binary.len()));
-                        loops += 1;
-                    }
-                    3 | 5 | 10 | 20 => {
-                        log::debug!("{}", match buf[0] {
-                                3 => "push stack",
-                                5 => "break",
-                                10 => "move_up",
-                                20 => "store",
-                                _ => "Unknown",
-                            });
-                        if let Ok(s) = read_f32(&mut binary, &mut reader) {
-                            max_stack_idx = max_stack_idx.max(s as _);
-                        }
-                    }
-                    4 => {
-                        log::debug!("allocated");
-                        //allocate needn't execute
-                        binary.pop().unwrap();
-                    }
-                    6 => {
-                        log::debug!("wait");
-                        max_stack_idx = max_stack_idx.max(read_f32(&mut binary, &mut reader).unwrap() as _);
-                    }
-                    11 => {
-                        log::debug!("summon_e");
-                        //name
-                        read_str(&mut reader, &mut binary, true);
-
-                        //xyz hp
-                        max_stack_idx = max_stack_idx.max(read_f32(&mut binary, &mut reader).unwrap() as _);
-                        max_stack_idx = max_stack_idx.max(read_f32(&mut binary, &mut reader).unwrap() as _);
-                        max_stack_idx = max_stack_idx.max(read_f32(&mut binary, &mut reader).unwrap() as _);
-                        max_stack_idx = max_stack_idx.max(read_f32(&mut binary, &mut reader).unwrap() as _);
-                        //collide & args
-                        reader.read(&mut buf[0..1]).unwrap();
-                        binary.push(buf[0]);
-
-                        for _ in 0..GameData::try_from(buf[0]).unwrap().get_args_count() {
-                            max_stack_idx = max_stack_idx.max(read_f32(&mut binary, &mut reader).unwrap() as _);
-                        }
-                        //ai & args
-                        let _script_name = read_str(&mut reader, &mut binary, true);
-                        while let Ok(s) = read_f32(&mut binary, &mut reader) {
-                            max_stack_idx = max_stack_idx.max(s as _);
-                        }
-                    }
-                    12 => {
-                        log::debug!("summon_b");
-                        //name
-                        read_str(&mut reader, &mut binary, true);
-
-                        //xyz scale angle
-                        max_stack_idx = max_stack_idx.max(read_f32(&mut binary, &mut reader).unwrap() as _);
-                        max_stack_idx = max_stack_idx.max(read_f32(&mut binary, &mut reader).unwrap() as _);
-                        max_stack_idx = max_stack_idx.max(read_f32(&mut binary, &mut reader).unwrap() as _);
-                        max_stack_idx = max_stack_idx.max(read_f32(&mut binary, &mut reader).unwrap() as _);
-                        max_stack_idx = max_stack_idx.max(read_f32(&mut binary, &mut reader).unwrap() as _);
-                        //collide & args
-                        reader.read(&mut buf[0..1]).unwrap();
-                        binary.push(buf[0]);
-
-                        for _ in 0..GameData::try_from(buf[0]).unwrap().get_args_count() {
-                            max_stack_idx = max_stack_idx.max(read_f32(&mut binary, &mut reader).unwrap() as _);
-                        }
-                        //ai & args
-                        let _script_name = read_str(&mut reader, &mut binary, true);
-                        while let Ok(s) = read_f32(&mut binary, &mut reader) {
-                            max_stack_idx = max_stack_idx.max(s as _);
-                        }
-                    }
-                    38 | 39 => {
-                        log::debug!("sin/cos command{}", buf[0]);
-                        if let Ok(s) = read_f32(&mut binary, &mut reader) {
-                            max_stack_idx = max_stack_idx.max(s as _);
-                        }
-                        if let Ok(s) = read_f32(&mut binary, &mut reader) {
-                            max_stack_idx = max_stack_idx.max(s as _);
-                        }
-                    }
-                    _ => {
-                        log::debug!("byte command{}", buf[0]);
-                    }
-                }
-            }
-            let function_desc = FunctionDesc {
-                code: binary,
-                loops: loop_vec,
-                max_stack: (max_stack_idx + 1) as u16,
-                thread_safe: true,
-            };
-            functions.insert(function_name, function_desc);
+        let version = u32::from_be_bytes(bytes[0..4].try_into().unwrap());
+        if version != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("[pool_script]unsupported version {}", version)));
         }
+        let data_count = bytes[4];
+        let functions = parse_functions_v0(&mut BufReader::new(&bytes[5..]))?;
 
         let mut data_map = HashMap::default();
         for x in 0..data_count {
@@ -235,223 +179,302 @@ impl PoolScriptBin {
         Ok(Self {
             version,
             data: data_map,
+            data_types: vec![DataType::F32; data_count as usize],
+            strings: vec![],
             functions,
         })
     }
 
-    pub fn save(&self, writer: &mut BufWriter<File>) -> Result<(), Error> {
-        writer.write(&self.version.to_be_bytes())?;
-        writer.write(&[self.data.len() as u8])?;
-        for x in self.functions.values() {
-            writer.write(&x.code)?;
-        }
+    /// Save the binary in the v1 container
+    pub fn save<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        writer.write_all(&container::write(self)?)?;
         writer.flush()?;
         Ok(())
     }
 }
 
-fn parse_data(parser: &mut Parser, data: &mut HashMap<String, u8>) -> Result<(), Error> {
-    loop {
-        let mut line = String::new();
-        parser.read_line(&mut line).unwrap();
-        let line = line.trim();
-        if line == "end" {
-            return Ok(());
+/// Return the function and the functions called by it
+fn compile_function(function: &Function, context: &mut Context, diagnostics: &mut Vec<Diagnostic>) -> (FunctionDesc, Vec<String>) {
+    let mut binary = vec![];
+    //the args are the first vars in the stack
+    for param in &function.params {
+        if context.find_index(&param.name).is_ok() {
+            diagnostics.push(Diagnostic::error(format!("param `{}` is declared already", param.name))
+                .with_span(param.span));
         }
-        let type_with_name: Vec<&str> = line.split(" ").collect();
-        if type_with_name.len() != 2 {
-            return Err(Error::new(ErrorKind::InvalidData, "[parse data]excepted 2 but found ".to_owned() + &type_with_name.len().to_string()));
-        }
-        if type_with_name[0] != "f32" {
-            return Err(Error::new(ErrorKind::InvalidData, "[parse data]only f32 is supported"));
-        }
-        data.insert(type_with_name[1].to_string(), data.len() as u8);
+        context.push_name(&param.name);
     }
+    compile_block(&function.body, context, &mut binary, diagnostics);
+    binary.push(0);
+
+    let info = context.take_info();
+    (FunctionDesc {
+        code: binary,
+        loops: info.loops,
+        max_stack: info.max_stack as u16,
+        max_calc: info.max_calc,
+        thread_safe: !info.random,
+    }, info.calls)
 }
 
-fn parse_function(name: &str, parser: &mut Parser, context: &mut Context) -> Result<FunctionDesc, Error> {
-    let name_bytes = name.bytes();
-    let mut binary: Vec<u8> = Vec::with_capacity(name_bytes.len() + 3);
-    for byte in (name_bytes.len() as u16).to_be_bytes().iter() {
-        binary.push(*byte);
-    }
-    for byte in name_bytes {
-        binary.push(byte);
+fn compile_block(body: &[Stmt], context: &mut Context, binary: &mut Vec<u8>, diagnostics: &mut Vec<Diagnostic>) {
+    for stmt in body {
+        if let Err(e) = compile_stmt(stmt, context, binary, diagnostics) {
+            diagnostics.push(e);
+        }
     }
+}
 
-    let mut loops = 0;
-    loop {
-        let mut raw_line = String::new();
-        let size = parser.read_line(&mut raw_line).unwrap();
-        if size == 0 {
-            break;
-        }
-        let line: Vec<&str> = raw_line.trim().splitn(2, " ").collect();
-        if line[0].is_empty() || line[0].starts_with("//") {
-            continue;
+fn compile_stmt(stmt: &Stmt, context: &mut Context, binary: &mut Vec<u8>, diagnostics: &mut Vec<Diagnostic>) -> Result<(), Diagnostic> {
+    match stmt {
+        Stmt::Loop(body) => {
+            context.push_stack();
+            binary.push(1);
+            context.push_loop(Loop::Start(binary.len()));
+            compile_block(body, context, binary, diagnostics);
+            binary.push(0);
+            context.push_loop(Loop::End(binary.len()));
+            context.pop_stack();
         }
-        match line[0] {
-            "end" => {
-                binary.push(0);
-                if loops > 0 {
-                    loops -= 1;
-                    context.pop_stack();
-                } else {
-                    break;
-                }
-            }
-            "move_up" => {
-                if let Ok(value) = context.parse_value(line[1]) {
-                    binary.push(10);
-                    value.flush(&mut binary)?;
-                } else {
-                    let exp = try_parse_expression(line[1].trim(), context)?;
-                    exp.flush(&mut binary)?;
-                    binary.push(10);
-                    binary.push(4);
-                }
-            }
-            "break" => {
-                if let Ok(value) = context.parse_value(line[1]) {
-                    binary.push(5);
-                    value.flush(&mut binary)?;
-                } else {
-                    let exp = try_parse_expression(line[1].trim(), context)?;
-                    exp.flush(&mut binary)?;
-                    binary.push(5);
-                    binary.push(4);
-                }
-            }
-            "wait" => {
-                if let Ok(value) = context.parse_value(line[1]) {
-                    binary.push(6);
-                    value.flush(&mut binary)?;
-                } else {
-                    let exp = try_parse_expression(line[1].trim(), context)?;
-                    exp.flush(&mut binary)?;
-                    binary.push(6);
-                    binary.push(4);
-                }
-            }
-            "loop" => {
-                loops += 1;
+        Stmt::If { cond, then, otherwise } => {
+            compile_operand(7, cond, true, context, binary)?;
+            let jump = binary.len();
+            binary.extend_from_slice(&[0, 0]);
+            context.push_stack();
+            compile_block(then, context, binary, diagnostics);
+            context.pop_stack();
+            if let Some(otherwise) = otherwise {
+                binary.push(8);
+                let else_jump = binary.len();
+                binary.extend_from_slice(&[0, 0]);
+                patch_jump(binary, jump, cond.span())?;
                 context.push_stack();
-                binary.push(1);
+                compile_block(otherwise, context, binary, diagnostics);
+                context.pop_stack();
+                patch_jump(binary, else_jump, cond.span())?;
+            } else {
+                patch_jump(binary, jump, cond.span())?;
             }
-            "summon_e" => summon_e(line[1], &context, &mut binary)?,
-            "summon_b" => summon_b(line[1], &context, &mut binary)?,
-            "kill" => {
-                binary.push(16);
+        }
+        Stmt::MoveUp(value) => compile_operand(10, value, false, context, binary)?,
+        Stmt::Break(value) => compile_operand(5, value, true, context, binary)?,
+        Stmt::Wait(value) => compile_operand(6, value, false, context, binary)?,
+        Stmt::SummonEnemy { name, values, collide, ai, args } => {
+            compile_summon(11, name, values, collide, ai, args, context, binary)?
+        }
+        Stmt::SummonBullet { name, values, collide, ai, args } => {
+            compile_summon(12, name, values, collide, ai, args, context, binary)?
+        }
+        Stmt::Kill => {
+            binary.push(16);
+        }
+        Stmt::DropItem { kind, count } => {
+            compile_operand(17, count, false, context, binary)?;
+            binary.push(*kind as u8);
+        }
+        Stmt::Call(call) => {
+            let exp = Expression::lower(&Expr::Call(call.clone()), context)?;
+            context.use_expression(&exp);
+            exp.flush(binary)?;
+            //drop the returned value
+            binary.push(14);
+        }
+        Stmt::Return(value) => {
+            if let Some(value) = value {
+                compile_operand(2, value, true, context, binary)?;
+            } else {
+                binary.push(2);
+                binary.push(9);
             }
-            "let" => {
-                let expression: Vec<&str> = line[1].split("=").collect();
-                let name = expression[0].trim();
-                if let Ok(index) = context.find_index(name) {
-                    if expression.len() < 2 {
-                        return Err(Error::new(ErrorKind::InvalidData, "[parse function]where is the expression? : ".to_owned() + &*raw_line));
-                    }
-                    let exp = try_parse_expression(expression[1].trim(), context)?;
-                    exp.flush(&mut binary)?;
-                    binary.push(20);
-                    index.flush(&mut binary)?;
-                } else {
-                    binary.push(4);
-                    context.push_name(name);
-                    if expression.len() > 1 {
-                        let exp = try_parse_expression(expression[1].trim(), context)?;
-                        exp.flush(&mut binary)?;
-                        binary.push(20);
-                        binary.push(3);
-                        if let ExpressionElement::STACK(idx) = context.find_index(name)? {
-                            binary.push(idx);
-                        } else {
-                            return Err(Error::new(ErrorKind::InvalidData, "[parse function]unknown reason: ".to_owned() + &*raw_line));
-                        }
-                    }
+        }
+        Stmt::Let { ty: None, name, value } if context.find_index(&name.name).is_ok() => {
+            let (index, var_ty) = context.find_var(&name.name)?;
+            check_writable(&index, name)?;
+            let value = value.as_ref().ok_or_else(|| {
+                Diagnostic::error(format!("missing expression for `{}`", name.name))
+                    .with_span(name.span)
+                    .with_note("it is declared already")
+            })?;
+            let exp = Expression::lower(value, context)?;
+            check_assign(var_ty, &exp, value.span())?;
+            context.use_expression(&exp);
+            exp.flush(binary)?;
+            binary.push(20);
+            index.flush(binary)?;
+        }
+        Stmt::Let { ty, name, value } => {
+            let exp = value.as_ref().map(|x| Expression::lower(x, context)).transpose()?;
+            let var_ty = match (ty, &exp) {
+                (Some(ty), _) => DataType::try_from(ty.name.as_str()).map_err(|_| unknown_type(ty))?,
+                //the number is f32 so it could be set to any number later
+                (None, Some(exp)) if !exp.ty().is_number() => exp.ty(),
+                _ => DataType::F32,
+            };
+            let is_vec2 = ty.is_none() && context.find_index(&format!("{}.x", name.name)).is_ok();
+            if var_ty == DataType::Vec2 || is_vec2 {
+                if let Some(value) = value {
+                    return Err(Diagnostic::error(format!("cannot set `{}` at once", name.name))
+                        .with_span(value.span())
+                        .with_note(format!("set `{0}.x` and `{0}.y` instead", name.name)));
                 }
+                for slot in DataType::Vec2.slot_names(&name.name) {
+                    context.push_var(&slot, DataType::F32);
+                }
+                return Ok(());
             }
-            "sin" | "cos" => {
-                let arg = line[1].trim().splitn(2, ",").collect::<Vec<&str>>();
-                if let (Ok(src), Ok(dst)) = (context.parse_value(arg[0].trim()), context.find_index(arg[1].trim())) {
-                    binary.push(if line[0] == "sin" { 38 } else { 39 });
-                    src.flush(&mut binary)?;
-                    dst.flush(&mut binary)?;
+            context.push_var(&name.name, var_ty);
+            if let (Some(exp), Some(value)) = (exp, value) {
+                check_assign(var_ty, &exp, value.span())?;
+                context.use_expression(&exp);
+                exp.flush(binary)?;
+                binary.push(20);
+                binary.push(3);
+                if let ExpressionElement::STACK(idx) = context.find_index(&name.name)? {
+                    binary.push(idx);
                 } else {
-                    return Err(Error::new(ErrorKind::InvalidData, "[parse function]unknown reason: ".to_owned() + &*raw_line));
+                    return Err(Diagnostic::error(format!("`{}` is not a local var", name.name))
+                        .with_span(name.span));
                 }
             }
-            _ => {
-                return Err(Error::new(ErrorKind::InvalidData, "[parse function]unknown command: ".to_owned() + &*raw_line));
+        }
+        Stmt::Sin { src, dst } | Stmt::Cos { src, dst } => {
+            compile_operand(if matches!(stmt, Stmt::Sin { .. }) { 38 } else { 39 }, src, false, context, binary)?;
+            let (index, ty) = context.find_var(&dst.name).map_err(|e| e.with_span(dst.span))?;
+            check_writable(&index, dst)?;
+            if ty != DataType::F32 {
+                return Err(Diagnostic::error(format!("expected `f32` var but `{}` is `{}`", dst.name, ty))
+                    .with_span(dst.span));
             }
+            index.flush(binary)?;
         }
     }
-    if loops > 0 {
-        Err(Error::new(ErrorKind::InvalidData, "[loops not end!] ".to_owned() + &*loops.to_string()))
-    } else {
-        //fixme: offer max_stack && loop vec
-        Ok(FunctionDesc {
-            code: binary,
-            loops: vec![],
-            max_stack: 0,
-            thread_safe: true,
-        })
+    Ok(())
+}
+
+/// Write the offset from the end of the offset bytes at `at` to the end of the binary
+fn patch_jump(binary: &mut [u8], at: usize, span: Span) -> Result<(), Diagnostic> {
+    let offset = u16::try_from(binary.len() - at - 2).map_err(|_| {
+        Diagnostic::error("the block is too long to jump over")
+            .with_span(span)
+            .with_note(format!("the code in the block should be at most {} bytes", u16::MAX))
+    })?;
+    binary[at..at + 2].copy_from_slice(&offset.to_be_bytes());
+    Ok(())
+}
+
+fn unknown_type(ty: &Ident) -> Diagnostic {
+    Diagnostic::error(format!("unknown data type `{}`", ty.name))
+        .with_span(ty.span)
+        .with_note("the types are f32, i32, bool, vec2 and str")
+}
+
+/// The value could be stored in the var of the type
+fn check_assign(var_ty: DataType, exp: &Expression, span: Span) -> Result<(), Diagnostic> {
+    if var_ty.accept(exp.ty()) {
+        return Ok(());
     }
+    let e = Diagnostic::error(format!("expected `{}` but found `{}`", var_ty, exp.ty()))
+        .with_span(span);
+    Err(if var_ty == DataType::I32 && exp.ty() == DataType::F32 {
+        e.with_note("use `int(...)` to drop the fraction")
+    } else {
+        e
+    })
 }
 
-fn summon_e(raw_args: &str, context: &Context, binary: &mut Vec<u8>) -> Result<(), Error> {
-    binary.push(11);
-    let args: Vec<&str> = raw_args.split(" ").collect();
-    if args.len() < 5 {
-        return Err(Error::new(ErrorKind::InvalidData, "[parse function]command args is not good (require 5..): ".to_owned() + raw_args));
-    }
-
-    // name x y z hp ai_name
-    args[0].flush(binary)?;
-    context.parse_value(args[1])?.flush(binary)?;
-    context.parse_value(args[2])?.flush(binary)?;
-    context.parse_value(args[3])?.flush(binary)?;
-    context.parse_value(args[4])?.flush(binary)?;
-    let collide_rule = GameData::try_from(args[5])?;
-    let read = collide_rule.get_args(&args[6..], context, binary)?;
-    let index = 6 + read;
-    args[index].flush(binary)?;
-    for x in args[index + 1..].iter() {
-        if let Ok(value) = context.parse_value(x) {
-            value.flush(binary)?;
-        } else {
-            x.flush(binary)?;
+/// Only the positions in the game data could be stored
+fn check_writable(index: &ExpressionElement, name: &Ident) -> Result<(), Diagnostic> {
+    match index {
+        ExpressionElement::GAME(idx) if *idx > GameData::PlayerZ as u8 => {
+            Err(Diagnostic::error(format!("`{}` is read only", name.name))
+                .with_span(name.span))
         }
+        _ => Ok(())
+    }
+}
+
+/// The value is a number, or a bool if it is allowed
+fn check_operand(exp: &Expression, allow_bool: bool, span: Span) -> Result<(), Diagnostic> {
+    if exp.ty().is_number() || (allow_bool && exp.ty() == DataType::Bool) {
+        Ok(())
+    } else {
+        Err(Diagnostic::error(format!("expected a number but found `{}`", exp.ty()))
+            .with_span(span))
+    }
+}
+
+/// The command with a value or an expression
+fn compile_operand(command: u8, value: &Expr, allow_bool: bool, context: &mut Context, binary: &mut Vec<u8>) -> Result<(), Diagnostic> {
+    let exp = Expression::lower(value, context)?;
+    check_operand(&exp, allow_bool, value.span())?;
+    if let Some(value) = exp.as_value() {
+        binary.push(command);
+        value.flush(binary)?;
+    } else {
+        context.use_expression(&exp);
+        exp.flush(binary)?;
+        binary.push(command);
+        binary.push(4);
+    }
+    Ok(())
+}
+
+/// The value which needn't calculate
+fn compile_value(value: &Expr, allow_bool: bool, context: &Context, binary: &mut Vec<u8>) -> Result<(), Diagnostic> {
+    let exp = Expression::lower(value, context)?;
+    check_operand(&exp, allow_bool, value.span())?;
+    let value = exp.as_value().ok_or_else(|| {
+        Diagnostic::error("expected a number or var but found expression")
+            .with_span(value.span())
+    })?;
+    value.flush(binary)?;
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn compile_summon(command: u8, name: &Name, values: &[Expr], collide: &Collide, ai: &Name, args: &[Expr],
+                  context: &Context, binary: &mut Vec<u8>) -> Result<(), Diagnostic> {
+    binary.push(command);
+    compile_name(name, context, binary)?;
+    for x in values {
+        compile_value(x, false, context, binary)?;
+    }
+    binary.push(collide.kind as u8);
+    for x in &collide.args {
+        compile_value(x, false, context, binary)?;
+    }
+    compile_name(ai, context, binary)?;
+    for x in args {
+        compile_value(x, true, context, binary)?;
     }
     binary.push(9);
     Ok(())
 }
 
-fn summon_b(raw_args: &str, context: &Context, binary: &mut Vec<u8>) -> Result<(), Error> {
-    binary.push(12);
-    let args: Vec<&str> = raw_args.split(" ").collect();
-    if args.len() < 7 {
-        return Err(Error::new(ErrorKind::InvalidData, "[parse function]command args is not good (require 7..): ".to_owned() + raw_args));
-    }
-
-    args[0].flush(binary)?;
-    context.parse_value(args[1])?.flush(binary)?;
-    context.parse_value(args[2])?.flush(binary)?;
-    context.parse_value(args[3])?.flush(binary)?;
-    context.parse_value(args[4])?.flush(binary)?;
-    context.parse_value(args[5])?.flush(binary)?;
-
-    let collide_rule = GameData::try_from(args[6])?;
-    let read = collide_rule.get_args(&args[7..], context, binary)?;
-    let index = 7 + read;
-    args[index].flush(binary)?;
-    for x in args[index + 1..].iter() {
-        if let Ok(value) = context.parse_value(x) {
+/// The literal name or the pointer to the str value
+fn compile_name(name: &Name, context: &Context, binary: &mut Vec<u8>) -> Result<(), Diagnostic> {
+    match name {
+        Name::Literal(name) => {
+            if name.name.len() >= DYNAMIC_NAME as usize {
+                return Err(Diagnostic::error("the name is too long")
+                    .with_span(name.span));
+            }
+            name.name.as_str().flush(binary)?;
+        }
+        Name::Dynamic(value) => {
+            let exp = Expression::lower(value, context)?;
+            if exp.ty() != DataType::Str {
+                return Err(Diagnostic::error(format!("expected `str` but found `{}`", exp.ty()))
+                    .with_span(value.span()));
+            }
+            let value = exp.as_value().ok_or_else(|| {
+                Diagnostic::error("expected a str or var but found expression")
+                    .with_span(value.span())
+            })?;
+            binary.extend_from_slice(&DYNAMIC_NAME.to_be_bytes());
             value.flush(binary)?;
-        } else {
-            x.flush(binary)?;
         }
     }
-    binary.push(9);
     Ok(())
 }
 
@@ -469,14 +492,288 @@ impl Compile for &str {
     }
 }
 
-/// 读f32的值 返回Ok则为堆栈值或0 Err则表示是否无值
-fn read_f32(binary: &mut Vec<u8>, reader: &mut BufReader<File>) -> Result<i16, bool> {
+/// The functions in version 0 have no header, so scan the commands to find the end and the loops
+///
+/// The allocate commands are dropped here.
+fn parse_functions_v0<R: Read>(reader: &mut BufReader<R>) -> Result<HashMap<String, FunctionDesc>, Error> {
+    let mut buf = [0; 16];
+    let mut functions = HashMap::new();
+    //the functions called by the function
+    let mut calls = HashMap::new();
+    loop {
+        let mut binary = Vec::with_capacity(128);
+        let mut max_stack_idx = -1i32;
+        let mut loop_vec = Vec::new();
+        let mut thread_safe = true;
+        let mut called = vec![];
+        if reader.fill_buf()?.is_empty() {
+            break;
+        }
+        let function_name = read_str(reader, &mut binary, false)?;
+        log::info!("Loading script function '{}'", function_name);
+        binary.clear();
+
+        let mut loops = 0;
+        //the allocate commands are dropped so the jump offsets should be fixed
+        let mut dropped = 0;
+        //(the offset index in binary, the target position in file)
+        let mut jumps: Vec<(usize, usize)> = vec![];
+        loop {
+            let position = binary.len() + dropped;
+            jumps.retain(|(at, target)| {
+                if *target == position {
+                    let offset = (binary.len() - at - 2) as u16;
+                    binary[*at..*at + 2].copy_from_slice(&offset.to_be_bytes());
+                    false
+                } else {
+                    true
+                }
+            });
+            if reader.read(&mut buf[0..1])? == 0 {
+                return Err(Error::new(ErrorKind::UnexpectedEof, format!("[pool_script]function {} is not end", function_name)));
+            }
+            binary.push(buf[0]);
+            match buf[0] {
+                0 => {
+                    if loops > 0 {
+                        log::debug!("end loop");
+                        loops -= 1;
+                        loop_vec.push(Loop::End(binary.len()));
+                    } else {
+                        log::debug!("return");
+                        break;
+                    }
+                }
+                1 => {
+                    log::debug!("loop");
+                    loop_vec.push(Loop::Start(binary.len()));
+                    loops += 1;
+                }
+                2 | 3 | 5 | 10 | 20 => {
+                    log::debug!("{}", match buf[0] {
+                            2 => "return",
+                            3 => "push stack",
+                            5 => "break",
+                            10 => "move_up",
+                            20 => "store",
+                            _ => "Unknown",
+                        });
+                    if let Some(s) = read_f32(&mut binary, reader)? {
+                        max_stack_idx = max_stack_idx.max(s as _);
+                    }
+                }
+                4 => {
+                    log::debug!("allocated");
+                    //allocate needn't execute
+                    binary.pop().unwrap();
+                    dropped += 1;
+                }
+                7 | 8 => {
+                    log::debug!("{}", if buf[0] == 7 { "jump_unless" } else { "jump" });
+                    if buf[0] == 7 {
+                        if let Some(s) = read_f32(&mut binary, reader)? {
+                            max_stack_idx = max_stack_idx.max(s as _);
+                        }
+                    }
+                    reader.read_exact(&mut buf[0..2])?;
+                    binary.push(buf[0]);
+                    binary.push(buf[1]);
+                    let offset = u16::from_be_bytes([buf[0], buf[1]]) as usize;
+                    jumps.push((binary.len() - 2, binary.len() + dropped + offset));
+                }
+                6 => {
+                    log::debug!("wait");
+                    max_stack_idx = max_stack_idx.max(read_f32(&mut binary, reader)?.unwrap_or(-1) as _);
+                }
+                13 => {
+                    let name = read_str(reader, &mut binary, true)?;
+                    reader.read_exact(&mut buf[0..1])?;
+                    binary.push(buf[0]);
+                    log::debug!("call {} with {} args", name, buf[0]);
+                    called.push(name);
+                }
+                53..=55 => {
+                    log::debug!("random command{}", buf[0]);
+                    //the random of the game is not shared between threads
+                    thread_safe = false;
+                }
+                11 => {
+                    log::debug!("summon_e");
+                    //name
+                    read_str(reader, &mut binary, true)?;
+
+                    //xyz hp
+                    max_stack_idx = max_stack_idx.max(read_f32(&mut binary, reader)?.unwrap_or(-1) as _);
+                    max_stack_idx = max_stack_idx.max(read_f32(&mut binary, reader)?.unwrap_or(-1) as _);
+                    max_stack_idx = max_stack_idx.max(read_f32(&mut binary, reader)?.unwrap_or(-1) as _);
+                    max_stack_idx = max_stack_idx.max(read_f32(&mut binary, reader)?.unwrap_or(-1) as _);
+                    //collide & args
+                    reader.read_exact(&mut buf[0..1])?;
+                    binary.push(buf[0]);
+
+                    for _ in 0..collide_args_count(buf[0])? {
+                        max_stack_idx = max_stack_idx.max(read_f32(&mut binary, reader)?.unwrap_or(-1) as _);
+                    }
+                    //ai & args
+                    let _script_name = read_str(reader, &mut binary, true)?;
+                    while let Some(s) = read_f32(&mut binary, reader)? {
+                        max_stack_idx = max_stack_idx.max(s as _);
+                    }
+                }
+                12 => {
+                    log::debug!("summon_b");
+                    //name
+                    read_str(reader, &mut binary, true)?;
+
+                    //xyz scale angle
+                    max_stack_idx = max_stack_idx.max(read_f32(&mut binary, reader)?.unwrap_or(-1) as _);
+                    max_stack_idx = max_stack_idx.max(read_f32(&mut binary, reader)?.unwrap_or(-1) as _);
+                    max_stack_idx = max_stack_idx.max(read_f32(&mut binary, reader)?.unwrap_or(-1) as _);
+                    max_stack_idx = max_stack_idx.max(read_f32(&mut binary, reader)?.unwrap_or(-1) as _);
+                    max_stack_idx = max_stack_idx.max(read_f32(&mut binary, reader)?.unwrap_or(-1) as _);
+                    //collide & args
+                    reader.read_exact(&mut buf[0..1])?;
+                    binary.push(buf[0]);
+
+                    for _ in 0..collide_args_count(buf[0])? {
+                        max_stack_idx = max_stack_idx.max(read_f32(&mut binary, reader)?.unwrap_or(-1) as _);
+                    }
+                    //ai & args
+                    let _script_name = read_str(reader, &mut binary, true)?;
+                    while let Some(s) = read_f32(&mut binary, reader)? {
+                        max_stack_idx = max_stack_idx.max(s as _);
+                    }
+                }
+                38 | 39 => {
+                    log::debug!("sin/cos command{}", buf[0]);
+                    if let Some(s) = read_f32(&mut binary, reader)? {
+                        max_stack_idx = max_stack_idx.max(s as _);
+                    }
+                    if let Some(s) = read_f32(&mut binary, reader)? {
+                        max_stack_idx = max_stack_idx.max(s as _);
+                    }
+                }
+                _ => {
+                    log::debug!("byte command{}", buf[0]);
+                }
+            }
+        }
+        if !jumps.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData, format!("[pool_script]bad jump in function {}", function_name)));
+        }
+        let function_desc = FunctionDesc {
+            code: binary,
+            loops: loop_vec,
+            max_stack: (max_stack_idx + 1) as u16,
+            //the calc stack is not counted in version 0
+            max_calc: CALC_STACK_SIZE as u8,
+            thread_safe,
+        };
+        calls.insert(function_name.clone(), called);
+        functions.insert(function_name, function_desc);
+    }
+    propagate_thread_safe(&mut functions, &calls);
+    Ok(functions)
+}
+
+/// Write the functions with the headers, the loader needn't scan the code
+pub(crate) fn write_functions(functions: &HashMap<String, FunctionDesc>, binary: &mut Vec<u8>) -> Result<(), Error> {
+    //keep the output stable between compilations
+    let mut functions = functions.iter().collect::<Vec<_>>();
+    functions.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, x) in functions {
+        name.as_str().flush(binary)?;
+        binary.extend_from_slice(&(x.code.len() as u32).to_be_bytes());
+        binary.extend_from_slice(&x.max_stack.to_be_bytes());
+        binary.push(x.max_calc);
+        binary.push(if x.thread_safe { FLAG_THREAD_SAFE } else { 0 });
+        binary.extend_from_slice(&(x.loops.len() as u32).to_be_bytes());
+        for l in &x.loops {
+            let (kind, position) = match l {
+                Loop::Start(position) => (1u8, position),
+                Loop::End(position) => (0, position),
+            };
+            binary.push(kind);
+            binary.extend_from_slice(&(*position as u32).to_be_bytes());
+        }
+        binary.extend_from_slice(&x.code);
+    }
+    Ok(())
+}
+
+/// Read the functions written by [`write_functions`]
+pub(crate) fn parse_functions<R: Read>(reader: &mut BufReader<R>) -> Result<HashMap<String, FunctionDesc>, Error> {
+    let mut functions = HashMap::new();
+    while !reader.fill_buf()?.is_empty() {
+        let name = read_str(reader, &mut vec![], false)?;
+        log::info!("Loading script function '{}'", name);
+        let code_len = read_u32(reader)? as u64;
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf)?;
+        let max_stack = u16::from_be_bytes([buf[0], buf[1]]);
+        let max_calc = buf[2];
+        let thread_safe = buf[3] & FLAG_THREAD_SAFE != 0;
+        let mut loops = vec![];
+        for _ in 0..read_u32(reader)? {
+            reader.read_exact(&mut buf[0..1])?;
+            let position = read_u32(reader)? as usize;
+            loops.push(if buf[0] == 0 { Loop::End(position) } else { Loop::Start(position) });
+        }
+        let mut code = vec![];
+        reader.by_ref().take(code_len).read_to_end(&mut code)?;
+        if code.len() as u64 != code_len {
+            return Err(Error::new(ErrorKind::UnexpectedEof, format!("[pool_script]function {} is not end", name)));
+        }
+        functions.insert(name, FunctionDesc {
+            code,
+            loops,
+            max_stack,
+            max_calc,
+            thread_safe,
+        });
+    }
+    Ok(functions)
+}
+
+/// The function calling the unsafe function is unsafe too
+fn propagate_thread_safe(functions: &mut HashMap<String, FunctionDesc>, calls: &HashMap<String, Vec<String>>) {
+    loop {
+        let unsafe_functions = calls.iter()
+            .filter(|(name, called)| functions[*name].thread_safe
+                && called.iter().any(|x| functions.get(x).map(|x| !x.thread_safe).unwrap_or(false)))
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        if unsafe_functions.is_empty() {
+            break;
+        }
+        for name in unsafe_functions {
+            functions.get_mut(&name).unwrap().thread_safe = false;
+        }
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, Error> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn collide_args_count(byte: u8) -> Result<usize, Error> {
+    if byte == GameData::CircleCollide as u8 {
+        Ok(GameData::CircleCollide.get_args_count())
+    } else {
+        Err(Error::new(ErrorKind::InvalidData, format!("[pool_script]{} is not a collide", byte)))
+    }
+}
+
+/// 读f32的值 返回Some则为堆栈值或-1 None则表示无值
+fn read_f32<R: Read>(binary: &mut Vec<u8>, reader: &mut BufReader<R>) -> Result<Option<i16>, Error> {
     let mut buf = [0; 4];
-    reader.read(&mut buf[0..1]).unwrap();
+    reader.read_exact(&mut buf[0..1])?;
     binary.push(buf[0]);
     match buf[0] {
         0 => {
-            reader.read(&mut buf[0..4]).unwrap();
+            reader.read_exact(&mut buf[0..4])?;
             log::debug!("read f32 point const: {}", f32::from_be_bytes(buf as _));
             binary.push(buf[0]);
             binary.push(buf[1]);
@@ -485,58 +782,197 @@ fn read_f32(binary: &mut Vec<u8>, reader: &mut BufReader<File>) -> Result<i16, b
         }
         3 => {
             log::debug!("read f32 from point stack value ({})", buf[0]);
-            reader.read(&mut buf[0..1]).unwrap();
+            reader.read_exact(&mut buf[0..1])?;
             binary.push(buf[0]);
-            return Ok(buf[0] as _);
+            return Ok(Some(buf[0] as _));
         }
         4 => {
             log::debug!("read f32 from pop calc value ({})", buf[0]);
         }
         9 => {
             log::debug!("no data");
-            return Err(true);
+            return Ok(None);
         }
         _ => {
             log::debug!("Read f32 from the point value ({})", buf[0]);
-            reader.read(&mut buf[0..1]).unwrap();
+            reader.read_exact(&mut buf[0..1])?;
             log::debug!("    the idx is {}", buf[0]);
             binary.push(buf[0]);
         }
     }
-    Ok(-1)
+    Ok(Some(-1))
 }
 
-fn read_str(reader: &mut BufReader<File>, binary: &mut Vec<u8>, write: bool) -> String {
-    let mut buf = [0; 32];
-    if reader.read(&mut buf[0..2]).unwrap() < 2 {
-        return "".to_string();
-    }
+pub(crate) fn read_str<R: Read>(reader: &mut BufReader<R>, binary: &mut Vec<u8>, write: bool) -> Result<String, Error> {
+    let mut buf = [0; 2];
+    reader.read_exact(&mut buf)?;
     if write {
         binary.push(buf[0]);
         binary.push(buf[1]);
     }
-    let str_len = u16::from_be_bytes(buf[0..2].try_into().unwrap()) as usize;
-    let mut vec = Vec::with_capacity(str_len);
-    let mut len = 0;
-    while len < str_len {
-        let read = reader.read(&mut buf[0..(str_len - len).min(32)]).unwrap();
-        if read == 0 {
-            log::error!("EOF but read str...?");
-            return "".to_string();
-        }
-        for x in &buf[0..read] {
-            vec.push(*x);
-        }
-        len += read;
-    }
+    let str_len = u16::from_be_bytes(buf) as usize;
+    let mut vec = vec![0; str_len];
+    reader.read_exact(&mut vec)?;
 
     if write {
-        for x in &vec {
-            binary.push(*x);
-        }
+        binary.extend_from_slice(&vec);
     }
 
-    let str = String::from_utf8(vec).expect("parse utf8 str failed");
+    let str = String::from_utf8(vec).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
     log::debug!("str: {}", str);
-    str
+    Ok(str)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::BufReader;
+
+    use crate::diagnostic::Span;
+    use crate::pool_script::{Loop, Parser, PoolScriptBin};
+
+    #[test]
+    fn report_all_errors() {
+        let source = "data
+    f32 speed
+    f64 count
+end
+
+function tick
+    move_up speeed
+    let a = speed + (1
+    jump 1
+    sin speed
+    summon_e circle_blue 0 0 0 10 square 1 normal
+end
+";
+        let diagnostics = Parser::new(source.as_bytes()).try_parse().unwrap_err();
+        let spans = diagnostics.iter().map(|x| (x.span, x.message.as_str())).collect::<Vec<_>>();
+        assert_eq!(spans, vec![
+            (Span { line: 3, column: 4, len: 3 }, "unknown data type `f64`"),
+            (Span { line: 7, column: 12, len: 6 }, "unknown var name `speeed`"),
+            (Span { line: 8, column: 22, len: 0 }, "expected `)` but found end of line"),
+            (Span { line: 9, column: 4, len: 4 }, "unknown command `jump`"),
+            (Span { line: 10, column: 13, len: 0 }, "expected `,` but found end of line"),
+            (Span { line: 11, column: 34, len: 6 }, "unknown collide type `square`"),
+        ]);
+    }
+
+    #[test]
+    fn check_calls() {
+        let source = "function tick
+    call aim(1)
+    let a = call fire()
+    call tick()
+end
+
+function aim(x, y)
+    return x + y
+end
+";
+        let diagnostics = Parser::new(source.as_bytes()).try_parse().unwrap_err();
+        let spans = diagnostics.iter().map(|x| (x.span, x.message.as_str())).collect::<Vec<_>>();
+        assert_eq!(spans, vec![
+            (Span { line: 2, column: 4, len: 11 }, "function `aim` takes 2 args but 1 are given"),
+            (Span { line: 3, column: 17, len: 4 }, "unknown function `fire`"),
+            (Span { line: 4, column: 9, len: 4 }, "unknown function `tick`"),
+        ]);
+    }
+
+    #[test]
+    fn check_types() {
+        let source = "data
+    i32 count
+    vec2 target
+    str texture
+end
+
+function tick
+    let count = count / 2
+    let texture = 1
+    let target = 0
+    let bool alive = pos_x > 0
+    move_up alive
+    let alive = count
+    let i32 n = int(pos_x) % 3
+    let n = texture + 1
+    sin 1, count
+    let name = \"red\"
+    let texture = name
+    summon_b (count) 0 0 0 1 0 circle 1 (name)
+    let self_hp = 0
+end
+";
+        let diagnostics = Parser::new(source.as_bytes()).try_parse().unwrap_err();
+        let spans = diagnostics.iter().map(|x| (x.span, x.message.as_str())).collect::<Vec<_>>();
+        assert_eq!(spans, vec![
+            (Span { line: 8, column: 16, len: 9 }, "expected `i32` but found `f32`"),
+            (Span { line: 9, column: 18, len: 1 }, "expected `str` but found `i32`"),
+            (Span { line: 10, column: 17, len: 1 }, "cannot set `target` at once"),
+            (Span { line: 12, column: 12, len: 5 }, "expected a number but found `bool`"),
+            (Span { line: 13, column: 16, len: 5 }, "expected `bool` but found `i32`"),
+            (Span { line: 15, column: 12, len: 11 }, "cannot use `+` on `str` and `i32`"),
+            (Span { line: 16, column: 11, len: 5 }, "expected `f32` var but `count` is `i32`"),
+            (Span { line: 19, column: 14, len: 5 }, "expected `str` but found `i32`"),
+            (Span { line: 20, column: 8, len: 7 }, "`self_hp` is read only"),
+        ]);
+    }
+
+    #[test]
+    fn random_is_not_thread_safe() {
+        let source = "function tick
+    move_up call speed()
+end
+
+function speed
+    return call base() + rand_range(1, 2)
+end
+
+function base
+    return 1
+end
+";
+        let bin = Parser::new(source.as_bytes()).try_parse().unwrap();
+        let mut binary = vec![];
+        bin.save(&mut binary).unwrap();
+        let bin = PoolScriptBin::try_parse_bin(BufReader::new(&binary[..])).unwrap();
+        assert!(!bin.functions["tick"].thread_safe);
+        assert!(!bin.functions["speed"].thread_safe);
+        assert!(bin.functions["base"].thread_safe);
+    }
+
+    #[test]
+    fn compile_function_header() {
+        let source = "function tick
+    let a = 1
+    loop
+        let b = a * (a + 1)
+        loop
+            break 1
+        end
+        move_up b
+    end
+end
+";
+        let bin = Parser::new(source.as_bytes()).try_parse().unwrap();
+        let tick = &bin.functions["tick"];
+        assert_eq!(tick.max_stack, 2);
+        assert_eq!(tick.max_calc, 3);
+        assert_eq!(tick.loops, vec![Loop::Start(10), Loop::Start(28), Loop::End(35), Loop::End(39)]);
+        let mut binary = vec![];
+        bin.save(&mut binary).unwrap();
+        let loaded = PoolScriptBin::try_parse_bin(BufReader::new(&binary[..])).unwrap();
+        assert_eq!(loaded.version, 1);
+        assert_eq!(loaded.functions["tick"].code, tick.code);
+        assert_eq!(loaded.functions["tick"].loops, tick.loops);
+        assert_eq!(loaded.functions["tick"].max_stack, 2);
+        assert_eq!(loaded.functions["tick"].max_calc, 3);
+    }
+
+    #[test]
+    fn warn_outside_function() {
+        let mut parser = Parser::new("tick\nfunction tick\nend\n".as_bytes());
+        assert!(parser.try_parse().is_ok());
+        assert_eq!(parser.diagnostics().len(), 1);
+        assert!(!parser.diagnostics()[0].is_error());
+    }
 }
@@ -0,0 +1,79 @@
+/// The xorshift64* random number generator used by the scripts
+///
+/// The same seed always gives the same numbers so the run could be replayed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Random {
+    state: u64,
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        //splitmix64 to spread the small seeds and the state cannot be zero
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Self {
+            state: if z == 0 { 0x9E37_79B9_7F4A_7C15 } else { z },
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// The value in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// The value in [min, max)
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    /// The integer in [0, n), 0 if n is less than 1
+    pub fn int(&mut self, n: f32) -> f32 {
+        let n = n.floor();
+        if n < 1.0 {
+            0.0
+        } else {
+            (self.next_u64() % n as u64) as f32
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::random::Random;
+
+    #[test]
+    fn same_seed_same_values() {
+        let mut a = Random::new(42);
+        let mut b = Random::new(42);
+        let mut c = Random::new(43);
+        let values = (0..100).map(|_| a.next_f32()).collect::<Vec<_>>();
+        assert_eq!(values, (0..100).map(|_| b.next_f32()).collect::<Vec<_>>());
+        assert_ne!(values, (0..100).map(|_| c.next_f32()).collect::<Vec<_>>());
+        for _ in 0..1000 {
+            let v = a.next_f32();
+            assert!((0.0..1.0).contains(&v));
+            let v = a.range(-3.0, 5.0);
+            assert!((-3.0..5.0).contains(&v));
+            let v = a.int(6.5);
+            assert!(v.fract() == 0.0 && (0.0..6.0).contains(&v));
+        }
+        assert_eq!(a.int(0.5), 0.0);
+    }
+}
@@ -0,0 +1,450 @@
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind};
+
+use crate::builtin::Builtin;
+use crate::data_type::DataType;
+use crate::game_data::{GameData, ItemKind};
+use crate::pool_script::{DYNAMIC_NAME, FunctionDesc, Loop, PoolScriptBin};
+
+/// The size of the calc stack in the runtime
+pub const CALC_STACK_SIZE: usize = 64;
+
+/// Check the functions so the runtime could execute them without checking the bounds
+///
+/// The calc stack of each function must be empty between the statements and never exceed `max_calc`,
+/// the jumps must stay in the same loop and the pointers must be in the range of the data or the stack.
+pub fn verify(bin: &PoolScriptBin) -> Result<(), Error> {
+    if bin.data_types.len() != bin.data.len() {
+        return Err(Error::new(ErrorKind::InvalidData, "[verify]the data types do not match the data"));
+    }
+    //report the same error for the same binary
+    let mut names = bin.functions.keys().collect::<Vec<_>>();
+    names.sort();
+    for name in names {
+        Verifier {
+            name,
+            desc: &bin.functions[name],
+            bin,
+            pointer: 0,
+            command: 0,
+            depth: 0,
+        }.verify()?;
+    }
+    Ok(())
+}
+
+struct Verifier<'a> {
+    name: &'a str,
+    desc: &'a FunctionDesc,
+    bin: &'a PoolScriptBin,
+    pointer: usize,
+    /// the position of the current command
+    command: usize,
+    /// the values in the calc stack pushed by this function
+    depth: usize,
+}
+
+impl<'a> Verifier<'a> {
+    fn verify(&mut self) -> Result<(), Error> {
+        let code = &self.desc.code;
+        if self.desc.max_calc as usize > CALC_STACK_SIZE {
+            return Err(self.error(format!("needs {} values in calc stack", self.desc.max_calc)));
+        }
+        //(the innermost loop, the calc depth) at the start of the commands
+        let mut starts = vec![None; code.len()];
+        let mut opened = vec![];
+        let mut loops = vec![];
+        //(the jump command, the target, the innermost loop)
+        let mut jumps = vec![];
+        loop {
+            if self.pointer >= code.len() {
+                return Err(self.error("the function is not end"));
+            }
+            self.command = self.pointer;
+            starts[self.command] = Some((opened.last().copied(), self.depth));
+            let command = self.read_byte()?;
+            match command {
+                0 => {
+                    self.check_empty()?;
+                    if opened.pop().is_some() {
+                        loops.push(Loop::End(self.pointer));
+                    } else {
+                        break;
+                    }
+                }
+                1 => {
+                    self.check_empty()?;
+                    opened.push(self.pointer);
+                    loops.push(Loop::Start(self.pointer));
+                }
+                2 => {
+                    self.read_pointer(true)?;
+                    self.check_empty()?;
+                }
+                3 => {
+                    self.read_pointer(false)?;
+                    self.push()?;
+                }
+                5 | 6 | 10 => {
+                    self.read_pointer(false)?;
+                    self.check_empty()?;
+                }
+                7 | 8 => {
+                    if command == 7 {
+                        self.read_pointer(false)?;
+                    }
+                    self.check_empty()?;
+                    let offset = self.read_bytes(2)?;
+                    let offset = u16::from_be_bytes([offset[0], offset[1]]) as usize;
+                    jumps.push((self.command, self.pointer + offset, opened.last().copied()));
+                }
+                11 | 12 => {
+                    self.read_name()?;
+                    for _ in 0..if command == 11 { 4 } else { 5 } {
+                        self.read_pointer(false)?;
+                    }
+                    let collide = self.read_byte()?;
+                    if collide != GameData::CircleCollide as u8 {
+                        return Err(self.error(format!("unknown collide {}", collide)));
+                    }
+                    for _ in 0..GameData::CircleCollide.get_args_count() {
+                        self.read_pointer(false)?;
+                    }
+                    self.read_name()?;
+                    while self.read_summon_arg()? {}
+                    self.check_empty()?;
+                }
+                13 => {
+                    let name = self.read_str()?;
+                    match self.bin.functions.get(name) {
+                        Some(function) if name != "tick" => {
+                            if self.desc.thread_safe && !function.thread_safe {
+                                return Err(self.error(format!("call {} which is not thread safe", name)));
+                            }
+                        }
+                        _ => return Err(self.error(format!("call unknown function {}", name)))
+                    }
+                    let args = self.read_byte()?;
+                    self.pop(args as usize)?;
+                    self.push()?;
+                }
+                14 => self.pop(1)?,
+                16 => self.check_empty()?,
+                17 => {
+                    self.read_pointer(false)?;
+                    let kind = self.read_byte()?;
+                    if ItemKind::try_from(kind).is_err() {
+                        return Err(self.error(format!("unknown item {}", kind)));
+                    }
+                    self.check_empty()?;
+                }
+                20 => {
+                    self.pop(1)?;
+                    self.read_target()?;
+                }
+                21..=31 => {
+                    self.pop(2)?;
+                    self.push()?;
+                }
+                38 | 39 => {
+                    self.read_pointer(false)?;
+                    self.read_target()?;
+                    self.check_empty()?;
+                }
+                41..=56 => {
+                    let builtin = Builtin::try_from(command)?;
+                    if self.desc.thread_safe && builtin.is_random() {
+                        return Err(self.error(format!("{} is not thread safe", builtin.name())));
+                    }
+                    self.pop(builtin.get_args_count())?;
+                    self.push()?;
+                }
+                _ => return Err(self.error(format!("unknown command {}", command)))
+            }
+        }
+        if self.pointer != code.len() {
+            return Err(self.error("the code after the end of function"));
+        }
+        for (command, target, scope) in jumps {
+            self.command = command;
+            match starts.get(target) {
+                Some(Some((target_scope, depth))) => {
+                    if *target_scope != scope {
+                        return Err(self.error(format!("jump to {} in another loop", target)));
+                    }
+                    if *depth != 0 {
+                        return Err(self.error(format!("jump to {} in the middle of expression", target)));
+                    }
+                }
+                _ => return Err(self.error(format!("jump to {} which is not a command", target)))
+            }
+        }
+        if loops != self.desc.loops {
+            self.command = 0;
+            return Err(self.error("the loops do not match the code"));
+        }
+        Ok(())
+    }
+
+    fn error<S: AsRef<str>>(&self, msg: S) -> Error {
+        Error::new(ErrorKind::InvalidData, format!("[verify]function {}: {} at {}", self.name, msg.as_ref(), self.command))
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Error> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let code: &'a [u8] = &self.desc.code;
+        let bytes = code.get(self.pointer..self.pointer + len)
+            .ok_or_else(|| self.error("unexpected end of function"))?;
+        self.pointer += len;
+        Ok(bytes)
+    }
+
+    fn read_str(&mut self) -> Result<&'a str, Error> {
+        let len = self.read_bytes(2)?;
+        let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+        let bytes = self.read_bytes(len)?;
+        std::str::from_utf8(bytes).map_err(|_| self.error("the string is not utf8"))
+    }
+
+    /// The literal name or the pointer to the str value
+    ///
+    /// The str in the stack is checked by the runtime because the types of the locals are unknown.
+    fn read_name(&mut self) -> Result<(), Error> {
+        let len = self.read_bytes(2)?;
+        if u16::from_be_bytes([len[0], len[1]]) != DYNAMIC_NAME {
+            self.pointer -= 2;
+            self.read_str()?;
+            return Ok(());
+        }
+        match self.read_byte()? {
+            0 => {
+                let value = self.read_bytes(4)?;
+                let value = f32::from_be_bytes([value[0], value[1], value[2], value[3]]);
+                if value.fract() != 0.0 || !(0.0..self.bin.strings.len() as f32).contains(&value) {
+                    return Err(self.error(format!("no string {}", value)));
+                }
+            }
+            2 => {
+                self.check_index(2)?;
+                let idx = self.desc.code[self.pointer - 1];
+                if self.bin.data_types[idx as usize] != DataType::Str {
+                    return Err(self.error(format!("the data {} is not str", idx)));
+                }
+            }
+            3 => self.check_index(3)?,
+            src => return Err(self.error(format!("data src {} is not str", src)))
+        }
+        Ok(())
+    }
+
+    /// Return false if there is no data
+    fn read_pointer(&mut self, optional: bool) -> Result<bool, Error> {
+        match self.read_byte()? {
+            0 => {
+                self.read_bytes(4)?;
+            }
+            4 => self.pop(1)?,
+            9 if optional => return Ok(false),
+            src @ 1..=3 => self.check_index(src)?,
+            src => return Err(self.error(format!("unknown data src {}", src)))
+        }
+        Ok(true)
+    }
+
+    /// The str value is an index to the strings of this script, the summoned script reads it in its own strings
+    fn read_summon_arg(&mut self) -> Result<bool, Error> {
+        if let Some(&[2, idx]) = self.desc.code.get(self.pointer..self.pointer + 2) {
+            if self.bin.data_types.get(idx as usize) == Some(&DataType::Str) {
+                return Err(self.error(format!("pass the str data {} to the summoned script", idx)));
+            }
+        }
+        self.read_pointer(true)
+    }
+
+    /// Read the pointer to store the value
+    fn read_target(&mut self) -> Result<(), Error> {
+        match self.read_byte()? {
+            1 => {
+                let idx = self.read_byte()?;
+                if idx > GameData::PlayerZ as u8 {
+                    return Err(self.error(format!("cannot store to game data {}", idx)));
+                }
+                Ok(())
+            }
+            src @ 2..=3 => self.check_index(src),
+            src => Err(self.error(format!("cannot store to data src {}", src)))
+        }
+    }
+
+    fn check_index(&mut self, src: u8) -> Result<(), Error> {
+        let idx = self.read_byte()?;
+        let valid = match src {
+            1 => idx <= GameData::PlayerGraze as u8 && idx != GameData::CircleCollide as u8,
+            2 => (idx as usize) < self.bin.data.len(),
+            _ => (idx as u16) < self.desc.max_stack,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(self.error(format!("the index {} of data src {} is out of range", idx, src)))
+        }
+    }
+
+    fn push(&mut self) -> Result<(), Error> {
+        if self.depth >= self.desc.max_calc as usize {
+            return Err(self.error("calc stack overflow"));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn pop(&mut self, count: usize) -> Result<(), Error> {
+        if self.depth < count {
+            return Err(self.error("pop the empty calc stack"));
+        }
+        self.depth -= count;
+        Ok(())
+    }
+
+    fn check_empty(&self) -> Result<(), Error> {
+        if self.depth != 0 {
+            return Err(self.error(format!("{} values left in calc stack", self.depth)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::io::BufReader;
+
+    use crate::pool_script::{FunctionDesc, Parser, PoolScriptBin};
+    use crate::random::Random;
+
+    fn parse(code: &[u8]) -> Result<PoolScriptBin, String> {
+        //one data and the function tick
+        let mut binary = vec![0, 0, 0, 0, 1, 0, 4];
+        binary.extend_from_slice(b"tick");
+        binary.extend_from_slice(code);
+        PoolScriptBin::try_parse_bin(BufReader::new(&binary[..])).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn reject_malformed() {
+        assert!(parse(&[10, 2, 0, 0]).is_ok());
+        assert_eq!(parse(&[10, 2, 5, 0]).unwrap_err(), "[verify]function tick: the index 5 of data src 2 is out of range at 0");
+        assert_eq!(parse(&[3, 2, 0, 0]).unwrap_err(), "[verify]function tick: 1 values left in calc stack at 3");
+        assert_eq!(parse(&[3, 2, 0, 21, 14, 0]).unwrap_err(), "[verify]function tick: pop the empty calc stack at 3");
+        assert_eq!(parse(&[6, 9, 0]).unwrap_err(), "[verify]function tick: unknown data src 9 at 0");
+        assert_eq!(parse(&[20, 0, 0, 0, 0, 0, 0]).unwrap_err(), "[verify]function tick: pop the empty calc stack at 0");
+        assert_eq!(parse(&[13, 0, 4, b't', b'i', b'c', b'k', 0, 14, 0]).unwrap_err(), "[verify]function tick: call unknown function tick at 0");
+        assert_eq!(parse(&[40, 0]).unwrap_err(), "[verify]function tick: unknown command 40 at 0");
+        //into the const of wait
+        assert_eq!(parse(&[8, 0, 1, 6, 0, 0, 0, 0, 0, 0]).unwrap_err(), "[pool_script]bad jump in function tick");
+        //out of the loop
+        assert_eq!(parse(&[1, 8, 0, 1, 0, 16, 0]).unwrap_err(), "[verify]function tick: jump to 5 in another loop at 1");
+        assert!(parse(&[1, 8, 0, 1, 16, 0, 0]).is_ok());
+        assert!(parse(&[3, 2, 0, 7, 4, 0, 1, 16, 0]).is_ok());
+    }
+
+    fn parse_header(max_calc: u8, thread_safe: bool, code: &[u8]) -> Result<PoolScriptBin, String> {
+        let mut functions = HashMap::new();
+        functions.insert("tick".to_string(), FunctionDesc {
+            code: code.to_vec(),
+            loops: vec![],
+            max_stack: 0,
+            max_calc,
+            thread_safe,
+        });
+        let bin = PoolScriptBin {
+            version: 1,
+            data: HashMap::new(),
+            data_types: vec![],
+            strings: vec![],
+            functions,
+        };
+        let mut binary = vec![];
+        bin.save(&mut binary).unwrap();
+        PoolScriptBin::try_parse_bin(BufReader::new(&binary[..])).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn reject_str_summon_arg() {
+        let source = "data
+    str tex
+    f32 speed
+end
+
+function tick
+    summon_b bullet 0 0 0 1 90 circle 1 normal speed
+end
+";
+        let mut bin = Parser::new(source.as_bytes()).try_parse().unwrap();
+        let mut binary = vec![];
+        bin.save(&mut binary).unwrap();
+        assert!(PoolScriptBin::try_parse_bin(BufReader::new(&binary[..])).is_ok());
+        //the compiler rejects it, but the binary could be written by others
+        bin.data_types.swap(0, 1);
+        let mut binary = vec![];
+        bin.save(&mut binary).unwrap();
+        let error = PoolScriptBin::try_parse_bin(BufReader::new(&binary[..])).unwrap_err().to_string();
+        assert!(error.contains("pass the str data 1 to the summoned script"), "{}", error);
+    }
+
+    #[test]
+    fn reject_wrong_header() {
+        let push = [3, 0, 0, 0, 0, 0, 14, 0];
+        assert!(parse_header(1, true, &push).is_ok());
+        assert_eq!(parse_header(0, true, &push).unwrap_err(), "[verify]function tick: calc stack overflow at 0");
+        assert_eq!(parse_header(65, true, &push).unwrap_err(), "[verify]function tick: needs 65 values in calc stack at 0");
+        assert!(parse_header(1, false, &[53, 14, 0]).is_ok());
+        assert_eq!(parse_header(1, true, &[53, 14, 0]).unwrap_err(), "[verify]function tick: rand is not thread safe at 0");
+        assert!(parse_header(0, true, &[6, 1, 18, 0]).is_ok());
+        assert_eq!(parse_header(0, true, &[6, 1, 10, 0]).unwrap_err(), "[verify]function tick: the index 10 of data src 1 is out of range at 0");
+        assert_eq!(parse_header(1, true, &[3, 1, 6, 20, 1, 6, 0]).unwrap_err(), "[verify]function tick: cannot store to game data 6 at 3");
+        assert_eq!(parse_header(0, true, &[1, 0, 0]).unwrap_err(), "[verify]function tick: the loops do not match the code at 0");
+        assert_eq!(parse_header(0, true, &[1, 0]).unwrap_err(), "[verify]function tick: the function is not end at 1");
+        let mut summon = vec![12, 0xFF, 0xFF, 0, 0, 0, 0, 0];
+        for _ in 0..5 {
+            summon.extend_from_slice(&[0, 0, 0, 0, 0]);
+        }
+        summon.extend_from_slice(&[10, 0, 0, 0, 0, 0, 0, 1, b'a', 9, 0]);
+        assert_eq!(parse_header(0, true, &summon).unwrap_err(), "[verify]function tick: no string 0 at 0");
+    }
+
+    #[test]
+    fn random_bytes_never_panic() {
+        let mut random = Random::new(7);
+        for _ in 0..20000 {
+            let len = random.int(48.0) as usize;
+            let code = (0..len).map(|_| random.int(60.0) as u8).collect::<Vec<_>>();
+            let _ = parse(&code);
+            let _ = PoolScriptBin::try_parse_bin(BufReader::new(&code[..]));
+        }
+    }
+
+    #[test]
+    fn mutated_scripts_never_panic() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../pooltouhou/res/script");
+        let mut random = Random::new(8);
+        for entry in dir.read_dir().unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension() != Some("pthpsb".as_ref()) {
+                continue;
+            }
+            let binary = std::fs::read(path).unwrap();
+            for _ in 0..500 {
+                let mut binary = binary.clone();
+                for _ in 0..1 + random.int(3.0) as usize {
+                    let idx = random.int(binary.len() as f32) as usize;
+                    binary[idx] = random.next_u64() as u8;
+                }
+                let _ = PoolScriptBin::try_parse_bin(BufReader::new(&binary[..]));
+            }
+        }
+    }
+}
@@ -0,0 +1,1005 @@
+use std::convert::{TryFrom, TryInto};
+use std::io::{Error, ErrorKind};
+
+use crate::data_type::DataValue;
+use crate::debugger::{DebugState, ScriptDebug};
+use crate::game_data::{GameData, ItemKind};
+use crate::manager::{ScriptDesc, ScriptManager};
+use crate::pool_script::{DYNAMIC_NAME, FunctionDesc, Loop};
+use crate::random::Random;
+use crate::verify::CALC_STACK_SIZE;
+
+/// What the scripts read from and submit to the game running them
+///
+/// The VM only knows the game values by [`GameData`], the game decides what they are for the running object.
+pub trait ScriptHost {
+    /// The game value, it is never the collide
+    fn get(&self, data: GameData) -> f32;
+    /// Only the positions are stored, see [`GameData::is_writable`]
+    fn set(&mut self, data: GameData, value: f32);
+    fn submit(&mut self, command: ScriptCommand);
+    /// The stage random, only the functions not thread safe use it
+    fn random(&mut self) -> &mut Random;
+
+    /// The script failed at runtime like running out of the budget, the object is killed by default
+    fn error(&mut self, error: Error) {
+        log::error!("{}", error);
+        self.submit(ScriptCommand::Kill);
+    }
+}
+
+/// The nested calls could be at most this deep
+pub const MAX_CALL_DEPTH: usize = 64;
+
+/// The collide of the summoned object like `circle 5`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Collide {
+    pub kind: GameData,
+    pub args: Vec<f32>,
+}
+
+#[derive(Debug, Clone)]
+pub enum ScriptCommand {
+    Move(f32),
+    SummonEnemy(String, f32, f32, f32, f32, Collide, String, Vec<f32>),
+    SummonBullet(String, f32, f32, f32, f32, f32, Collide, String, Vec<f32>),
+    Kill,
+    /// the kind and the count of the items dropped at the object
+    DropItem(ItemKind, f32),
+}
+
+#[derive(Debug, Clone)]
+pub struct CalcStack {
+    data: [f32; CALC_STACK_SIZE],
+    len: usize,
+}
+
+impl Default for CalcStack {
+    fn default() -> Self {
+        Self {
+            data: [0.0; CALC_STACK_SIZE],
+            len: 0,
+        }
+    }
+}
+
+impl CalcStack {
+    pub fn pop(&mut self) -> Option<f32> {
+        self.len = self.len.checked_sub(1)?;
+        Some(self.data[self.len])
+    }
+
+    /// Return false if the stack is full
+    pub fn push(&mut self, v: f32) -> bool {
+        match self.data.get_mut(self.len) {
+            Some(x) => {
+                *x = v;
+                self.len += 1;
+                true
+            }
+            None => false
+        }
+    }
+
+    pub fn last_mut(&mut self) -> Option<&mut f32> {
+        self.values_mut().last_mut()
+    }
+
+    /// The values pushed, the top is the last
+    pub fn values(&self) -> &[f32] {
+        &self.data[..self.len]
+    }
+
+    fn values_mut(&mut self) -> &mut [f32] {
+        &mut self.data[..self.len]
+    }
+}
+
+pub struct ScriptContext {
+    /// the index of the script in the manager
+    pub desc_index: usize,
+    pub data: Vec<DataValue>,
+    tick_function: Option<FunctionContext>,
+    /// pauses the tick function when it is debugged
+    pub debug: Option<Box<ScriptDebug>>,
+}
+
+impl ScriptContext {
+    /// The args are converted to the types of the data
+    pub fn new(desc: &ScriptDesc, args: Vec<f32>) -> Self {
+        let data = desc.data_types.iter().enumerate()
+            .map(|(idx, ty)| DataValue::new(*ty, args.get(idx).copied().unwrap_or_default()))
+            .collect();
+        Self {
+            desc_index: desc.index,
+            data,
+            tick_function: desc.tick_function.as_ref().map(|f| FunctionContext::new(f.max_stack.into())),
+            debug: None,
+        }
+    }
+
+    /// Keep the data with the same name and type after the script is reloaded, the tick function starts again
+    pub fn migrate(&mut self, old: &ScriptDesc, new: &ScriptDesc) {
+        let mut data = new.data_types.iter().map(|ty| DataValue::new(*ty, 0.0)).collect::<Vec<_>>();
+        for (name, &index) in &new.data_index {
+            if let Some(&old_index) = old.data_index.get(name) {
+                if old.data_types[old_index as usize] == new.data_types[index as usize] {
+                    data[index as usize] = self.data[old_index as usize];
+                }
+            }
+        }
+        self.data = data;
+        self.tick_function = new.tick_function.as_ref().map(|f| FunctionContext::new(f.max_stack.into()));
+    }
+
+    /// The value of the data by the name
+    pub fn get_data(&self, script_manager: &ScriptManager, name: &str) -> Option<f32> {
+        let index = *script_manager.scripts[self.desc_index].data_index.get(name)?;
+        Some(self.data[index as usize].get())
+    }
+}
+
+impl ScriptContext {
+    pub fn execute_function<H: ScriptHost>(&mut self, name: &str, host: &mut H, script_manager: &ScriptManager) -> Option<f32> {
+        let function = script_manager.scripts.get(self.desc_index)
+            .unwrap().functions.get(name).expect("no such function.");
+        let mut function_context = FunctionContext::new(function.max_stack as usize);
+        let mut calc_stack = CalcStack::default();
+        let mut budget = script_manager.budget;
+        let mut function_runner = FunctionRunner {
+            desc_index: self.desc_index,
+            data: &mut self.data,
+            name,
+            desc: function,
+            host: &mut *host,
+            calc_stack: &mut calc_stack,
+            context: &mut function_context,
+            debug: None,
+            budget: &mut budget,
+            depth: 0,
+            offset: 0,
+        };
+        let result = unsafe {
+            function_runner.execute(script_manager)
+        };
+        report(result, host, &script_manager.scripts[self.desc_index].name)
+    }
+
+    /// The args are the first vars in the stack of the function
+    pub fn exe_fn_if_present<H: ScriptHost>(&mut self, name: &str, args: &[f32], host: &mut H, script_manager: &ScriptManager) -> Option<f32> {
+        if let Some(function) = script_manager.scripts.get(self.desc_index).unwrap().functions.get(name) {
+            let mut function_context = FunctionContext::new((function.max_stack as usize).max(args.len()));
+            function_context.var_stack[..args.len()].copy_from_slice(args);
+            let mut calc_stack = CalcStack::default();
+            let mut budget = script_manager.budget;
+            let mut function_runner = FunctionRunner {
+                desc_index: self.desc_index,
+                data: &mut self.data,
+                name,
+                desc: function,
+                host: &mut *host,
+                calc_stack: &mut calc_stack,
+                context: &mut function_context,
+                debug: None,
+                budget: &mut budget,
+                depth: 0,
+                offset: 0,
+            };
+            let result = unsafe {
+                function_runner.execute(script_manager)
+            };
+            report(result, host, &script_manager.scripts[self.desc_index].name)
+        } else {
+            None
+        }
+    }
+
+    /// Run the tick function if it could run in the parallel (`Some(true)`) or the sequential (`Some(false)`) pass
+    /// or anyway if `para` is None
+    pub fn tick_function<H: ScriptHost>(&mut self, host: &mut H, script_manager: &ScriptManager, para: Option<bool>) -> Option<f32> {
+        let script_desc = &script_manager.scripts[self.desc_index];
+        let desc = script_desc.tick_function
+            .as_ref().unwrap();
+        if para.map(|para| !(para ^ desc.thread_safe)).unwrap_or(true) {
+            let context = self.tick_function.as_mut().unwrap();
+            if context.wait > 0 {
+                context.wait -= 1;
+                return None;
+            }
+
+            let mut calc_stack = CalcStack::default();
+            let mut budget = script_manager.budget;
+            let mut function_runner = FunctionRunner {
+                desc_index: self.desc_index,
+                data: &mut self.data,
+                name: "tick",
+                desc,
+                host: &mut *host,
+                calc_stack: &mut calc_stack,
+                context: &mut *context,
+                debug: self.debug.as_deref_mut(),
+                budget: &mut budget,
+                depth: 0,
+                offset: 0,
+            };
+
+            let result = unsafe {
+                function_runner.execute(script_manager)
+            };
+            if result.is_err() {
+                //start again if the object is not killed by the host
+                context.reset();
+            }
+            report(result, host, &script_manager.scripts[self.desc_index].name)
+        } else {
+            None
+        }
+    }
+}
+
+/// Give the runtime error to the host with the name of the script
+fn report<H: ScriptHost>(result: Result<Option<f32>, Error>, host: &mut H, script: &str) -> Option<f32> {
+    match result {
+        Ok(value) => value,
+        Err(e) => {
+            host.error(Error::new(e.kind(), format!("[run script]{}::{}", script, e)));
+            None
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FunctionContext {
+    var_stack: Vec<f32>,
+    loop_start: Vec<usize>,
+    pointer: usize,
+    wait: i32,
+}
+
+impl FunctionContext {
+    fn new(max_stack: usize) -> Self {
+        let mut stack_vec = Vec::new();
+        stack_vec.resize_with(max_stack, || 0.0);
+        Self {
+            var_stack: stack_vec,
+            loop_start: Vec::with_capacity(2),
+            pointer: 0,
+            wait: 0,
+        }
+    }
+}
+
+
+impl FunctionContext {
+    #[inline]
+    fn reset(&mut self) {
+        self.loop_start.clear();
+        self.pointer = 0;
+    }
+}
+
+struct FunctionRunner<'a, H: ScriptHost> {
+    desc_index: usize,
+    data: &'a mut Vec<DataValue>,
+    name: &'a str,
+    desc: &'a FunctionDesc,
+    host: &'a mut H,
+    calc_stack: &'a mut CalcStack,
+    context: &'a mut FunctionContext,
+    debug: Option<&'a mut ScriptDebug>,
+    /// the commands could still run, shared by the called functions
+    budget: &'a mut u32,
+    /// the count of the callers
+    depth: usize,
+    /// the offset of the running command
+    offset: usize,
+}
+
+impl<'a, H: ScriptHost> FunctionRunner<'a, H> {
+    pub unsafe fn execute(&mut self, script_manager: &ScriptManager) -> Result<Option<f32>, Error> {
+        loop {
+            self.offset = self.context.pointer;
+            if let Some(debug) = self.debug.as_deref_mut() {
+                let state = DebugState {
+                    script: &script_manager.scripts[self.desc_index],
+                    function: self.desc,
+                    var_stack: &self.context.var_stack,
+                    data: &self.data[..],
+                    calc_stack: &*self.calc_stack,
+                };
+                if debug.pause_at(self.context.pointer, state) {
+                    return Ok(None);
+                }
+            }
+            if *self.budget == 0 {
+                return Err(self.error(format!("ran more than {} commands, is there a loop without wait?", script_manager.budget)));
+            }
+            *self.budget -= 1;
+            //SAFETY: we checked the bound when loading
+            let command = self.desc.code.get_unchecked(self.context.pointer);
+            self.context.pointer += 1;
+            match command {
+                0 => {
+                    if !self.context.loop_start.is_empty() {
+                        self.context.pointer = *self.context.loop_start.last().unwrap();
+                    } else {
+                        self.context.reset();
+                        break;
+                    }
+                }
+                1 => {
+                    self.context.loop_start.push(self.context.pointer);
+                }
+                2 => {
+                    let value = self.read_f32()?;
+                    self.context.reset();
+                    return Ok(value);
+                }
+                3 => {
+                    let data = self.read_f32_unchecked()?;
+                    self.push(data)?;
+                }
+                5 => {
+                    let times = self.read_f32_unchecked()?;
+                    if times >= 1.0 {
+                        let times = times.floor() as i32;
+                        for _ in 0..times {
+                            if self.context.loop_start.pop().is_some() {
+                                let mut layer = 0;
+                                for x in self.desc.loops.iter() {
+                                    match x {
+                                        Loop::Start(p) => {
+                                            if self.context.pointer < *p {
+                                                layer += 1;
+                                            }
+                                        }
+                                        Loop::End(p) => {
+                                            if self.context.pointer < *p {
+                                                if layer == 0 {
+                                                    self.context.pointer = *p;
+                                                    break;
+                                                }
+                                                layer -= 1;
+                                            }
+                                        }
+                                    }
+                                }
+                            } else {
+                                break;
+                            }
+                        }
+                    }
+                }
+                6 => {
+                    let wait = self.read_f32_unchecked()?.floor() as i32;
+                    if wait > 0 {
+                        self.context.wait = wait - 1;
+                        return Ok(None);
+                    }
+                }
+                7 => {
+                    let cond = self.read_f32_unchecked()?;
+                    let offset = self.read_offset();
+                    if cond == 0.0 {
+                        self.context.pointer += offset;
+                    }
+                }
+                8 => {
+                    let offset = self.read_offset();
+                    self.context.pointer += offset;
+                }
+                10 => {
+                    let v = self.read_f32()?;
+                    self.host.submit(ScriptCommand::Move(v.unwrap()));
+                }
+                11 => {
+                    let name = self.read_name(script_manager)?;
+                    let x = self.read_f32_unchecked()?;
+                    let y = self.read_f32_unchecked()?;
+                    let z = self.read_f32_unchecked()?;
+                    let hp = self.read_f32_unchecked()?;
+
+                    let collide = self.read_collide()?;
+
+                    let ai_name = self.read_name(script_manager)?;
+                    //the script may be loaded later by the host
+                    let arg_len = script_manager.get_script(&ai_name).map_or(0, |x| x.data_types.len());
+                    let mut args = Vec::with_capacity(arg_len);
+                    while let Some(arg) = self.read_f32()? {
+                        args.push(arg);
+                    }
+                    self.host.submit(ScriptCommand::SummonEnemy(name, x, y, z, hp, collide, ai_name, args));
+                }
+                12 => {
+                    let name = self.read_name(script_manager)?;
+                    let x = self.read_f32_unchecked()?;
+                    let y = self.read_f32_unchecked()?;
+                    let z = self.read_f32_unchecked()?;
+                    let scale = self.read_f32_unchecked()?;
+                    let angle = self.read_f32_unchecked()?;
+                    let collide = self.read_collide()?;
+                    let ai_name = self.read_name(script_manager)?;
+                    //the script may be loaded later by the host
+                    let arg_len = script_manager.get_script(&ai_name).map_or(0, |x| x.data_types.len());
+                    let mut args = Vec::with_capacity(arg_len);
+                    while let Some(arg) = self.read_f32()? {
+                        args.push(arg);
+                    }
+                    self.host.submit(ScriptCommand::SummonBullet(name, x, y, z, scale, angle, collide, ai_name, args));
+                }
+                13 => {
+                    let name = self.read_str();
+                    let arg_len = self.desc.code[self.context.pointer] as usize;
+                    self.context.pointer += 1;
+                    let value = self.call(&name, arg_len, script_manager)?;
+                    self.push(value.unwrap_or(0.0))?;
+                }
+                14 => {
+                    self.pop()?;
+                }
+                16 => {
+                    self.host.submit(ScriptCommand::Kill)
+                }
+                17 => {
+                    let count = self.read_f32()?.unwrap();
+                    let kind = self.desc.code[self.context.pointer];
+                    self.context.pointer += 1;
+                    let kind = ItemKind::try_from(kind).map_err(|_| self.error(format!("drop unknown item {}", kind)))?;
+                    self.host.submit(ScriptCommand::DropItem(kind, count));
+                }
+                20 => {
+                    let value = self.pop()?;
+                    self.store_unchecked_f32(value);
+                }
+                21 => {
+                    let x = self.pop()?;
+                    let y = self.last_mut()?;
+                    *y += x;
+                }
+                22 => {
+                    let x = self.pop()?;
+                    let y = self.last_mut()?;
+                    *y -= x;
+                }
+                23 => {
+                    let x = self.pop()?;
+                    let y = self.last_mut()?;
+                    *y *= x;
+                }
+                24 => {
+                    let x = self.pop()?;
+                    let y = self.last_mut()?;
+                    *y /= x;
+                }
+                25 => {
+                    let x = self.pop()?;
+                    let y = self.last_mut()?;
+                    *y %= x;
+                }
+                26 => {
+                    let x = self.pop()?;
+                    let y = self.last_mut()?;
+                    *y = if *y == x { 1.0 } else { 0.0 };
+                }
+                27 => {
+                    let x = self.pop()?;
+                    let y = self.last_mut()?;
+                    *y = if *y != x { 1.0 } else { 0.0 };
+                }
+                28 => {
+                    let x = self.pop()?;
+                    let y = self.last_mut()?;
+                    *y = if *y < x { 1.0 } else { 0.0 };
+                }
+                29 => {
+                    let x = self.pop()?;
+                    let y = self.last_mut()?;
+                    *y = if *y > x { 1.0 } else { 0.0 };
+                }
+                30 => {
+                    let x = self.pop()?;
+                    let y = self.last_mut()?;
+                    *y = if *y <= x { 1.0 } else { 0.0 };
+                }
+                31 => {
+                    let x = self.pop()?;
+                    let y = self.last_mut()?;
+                    *y = if *y >= x { 1.0 } else { 0.0 };
+                }
+                38 => {
+                    let mut v = self.read_f32_unchecked()?;
+                    v = (v * std::f32::consts::PI / 180.0).sin();
+                    self.store_unchecked_f32(v);
+                }
+                39 => {
+                    let mut v = self.read_f32_unchecked()?;
+                    v = (v * std::f32::consts::PI / 180.0).cos();
+                    self.store_unchecked_f32(v);
+                }
+                41 => {
+                    let y = self.last_mut()?;
+                    *y = y.to_radians().sin();
+                }
+                42 => {
+                    let y = self.last_mut()?;
+                    *y = y.to_radians().cos();
+                }
+                43 => {
+                    let x = self.pop()?;
+                    let y = self.last_mut()?;
+                    *y = y.atan2(x).to_degrees();
+                }
+                44 => {
+                    let y = self.last_mut()?;
+                    *y = y.sqrt();
+                }
+                45 => {
+                    let y = self.last_mut()?;
+                    *y = y.abs();
+                }
+                46 => {
+                    let x = self.pop()?;
+                    let y = self.last_mut()?;
+                    *y = y.min(x);
+                }
+                47 => {
+                    let x = self.pop()?;
+                    let y = self.last_mut()?;
+                    *y = y.max(x);
+                }
+                48 => {
+                    let y = self.last_mut()?;
+                    *y = y.floor();
+                }
+                49 => {
+                    let x = self.pop()?;
+                    let y = self.last_mut()?;
+                    *y = y.powf(x);
+                }
+                50 => {
+                    let t = self.pop()?;
+                    let b = self.pop()?;
+                    let a = self.last_mut()?;
+                    *a += (b - *a) * t;
+                }
+                51 => {
+                    let max = self.pop()?;
+                    let min = self.pop()?;
+                    let y = self.last_mut()?;
+                    *y = y.max(min).min(max);
+                }
+                52 => {
+                    let y = self.host.get(GameData::PlayerY) - self.host.get(GameData::PosY);
+                    let x = self.host.get(GameData::PlayerX) - self.host.get(GameData::PosX);
+                    let angle = y.atan2(x).to_degrees();
+                    self.push(angle)?;
+                }
+                53 => {
+                    let v = self.host.random().next_f32();
+                    self.push(v)?;
+                }
+                54 => {
+                    let max = self.pop()?;
+                    let min = self.pop()?;
+                    let v = self.host.random().range(min, max);
+                    self.push(v)?;
+                }
+                55 => {
+                    let n = self.pop()?;
+                    let v = self.host.random().int(n);
+                    self.push(v)?;
+                }
+                56 => {
+                    let y = self.last_mut()?;
+                    *y = y.trunc();
+                }
+                _ => return Err(self.error(format!("unknown command {}", command)))
+            }
+        }
+        Ok(None)
+    }
+    /// Run the function in a new frame with the args on the calc stack
+    ///
+    /// The frame is dropped after returning, so the function returns nothing if it waits.
+    unsafe fn call(&mut self, name: &str, arg_len: usize, script_manager: &ScriptManager) -> Result<Option<f32>, Error> {
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(self.error(format!("calls {} nested more than {} times", name, MAX_CALL_DEPTH)));
+        }
+        let function = script_manager.scripts[self.desc_index].functions.get(name)
+            .ok_or_else(|| self.error(format!("no function {}", name)))?;
+        let mut context = FunctionContext::new((function.max_stack as usize).max(arg_len));
+        for idx in (0..arg_len).rev() {
+            context.var_stack[idx] = self.pop()?;
+        }
+        let mut function_runner = FunctionRunner {
+            desc_index: self.desc_index,
+            data: &mut *self.data,
+            name,
+            desc: function,
+            host: &mut *self.host,
+            calc_stack: &mut *self.calc_stack,
+            context: &mut context,
+            debug: None,
+            budget: &mut *self.budget,
+            depth: self.depth + 1,
+            offset: 0,
+        };
+        function_runner.execute(script_manager)
+    }
+
+    /// The error at the running command
+    fn error<S: AsRef<str>>(&self, msg: S) -> Error {
+        Error::new(ErrorKind::InvalidData, format!("{} at {:04}: {}", self.name, self.offset, msg.as_ref()))
+    }
+
+    #[inline]
+    fn pop(&mut self) -> Result<f32, Error> {
+        match self.calc_stack.pop() {
+            Some(v) => Ok(v),
+            None => Err(self.error("calc stack underflow"))
+        }
+    }
+
+    #[inline]
+    fn push(&mut self, v: f32) -> Result<(), Error> {
+        if self.calc_stack.push(v) {
+            Ok(())
+        } else {
+            Err(self.error("calc stack overflow"))
+        }
+    }
+
+    #[inline]
+    fn last_mut(&mut self) -> Result<&mut f32, Error> {
+        if self.calc_stack.values().is_empty() {
+            return Err(self.error("calc stack underflow"));
+        }
+        Ok(self.calc_stack.last_mut().unwrap())
+    }
+
+    #[inline]
+    fn read_offset(&mut self) -> usize {
+        let offset = &self.desc.code[self.context.pointer..self.context.pointer + 2];
+        self.context.pointer += 2;
+        u16::from_be_bytes(offset.try_into().unwrap()) as usize
+    }
+
+    #[inline]
+    fn read_str(&mut self) -> String {
+        let count = &self.desc.code[self.context.pointer..self.context.pointer + 2];
+        let count = u16::from_be_bytes(count.try_into().unwrap());
+        self.context.pointer += 2;
+        let bytes = &self.desc.code[self.context.pointer..self.context.pointer + count as usize];
+        self.context.pointer += count as usize;
+        unsafe {
+            String::from_utf8_unchecked(bytes.to_vec())
+        }
+    }
+
+    /// The literal name or the str value
+    unsafe fn read_name(&mut self, script_manager: &ScriptManager) -> Result<String, Error> {
+        let len = &self.desc.code[self.context.pointer..self.context.pointer + 2];
+        if u16::from_be_bytes(len.try_into().unwrap()) != DYNAMIC_NAME {
+            return Ok(self.read_str());
+        }
+        self.context.pointer += 2;
+        let idx = self.read_f32_unchecked()?;
+        let strings = &script_manager.scripts[self.desc_index].strings;
+        //`as usize` turns the negative and the fractional values into other indexes
+        match strings.get(idx as usize) {
+            Some(name) if idx >= 0.0 && idx.fract() == 0.0 => Ok(name.clone()),
+            _ => Err(self.error(format!("no string {}", idx)))
+        }
+    }
+
+    #[inline]
+    unsafe fn store_unchecked_f32(&mut self, value: f32) {
+        let src = self.desc.code[self.context.pointer];
+        let index = self.desc.code[self.context.pointer + 1];
+        self.context.pointer += 2;
+        match src {
+            1 => {
+                self.host.set(GameData::try_from(index).unwrap(), value);
+            }
+            2 => {
+                self.data.get_unchecked_mut(index as usize).set(value);
+            }
+            3 => {
+                *self.context.var_stack.get_unchecked_mut(index as usize) = value;
+            }
+            _ => unreachable!("Unknown data src: {}", src)
+        }
+    }
+
+    #[inline]
+    unsafe fn read_f32_unchecked(&mut self) -> Result<f32, Error> {
+        let src = self.desc.code[self.context.pointer];
+        match src {
+            0 => {
+                let data = self.desc.code.get_unchecked(self.context.pointer + 1..self.context.pointer + 5)
+                    .try_into().unwrap();
+                self.context.pointer += 5;
+                Ok(f32::from_be_bytes(data))
+            }
+            1 => {
+                let data = *self.desc.code.get_unchecked(self.context.pointer + 1);
+                self.context.pointer += 2;
+                Ok(self.read_game(data))
+            }
+            2 => {
+                let data = *self.desc.code.get_unchecked(self.context.pointer + 1);
+                self.context.pointer += 2;
+                Ok(self.data.get_unchecked(data as usize).get())
+            }
+            3 => {
+                let data = *self.desc.code.get_unchecked(self.context.pointer + 1);
+                self.context.pointer += 2;
+                Ok(*self.context.var_stack.get_unchecked(data as usize))
+            }
+            4 => {
+                self.context.pointer += 1;
+                self.pop()
+            }
+            _ => Err(self.error(format!("unknown data src {}", src)))
+        }
+    }
+
+    fn read_game(&self, index: u8) -> f32 {
+        self.host.get(GameData::try_from(index).unwrap())
+    }
+
+    /// The collide byte and its args
+    unsafe fn read_collide(&mut self) -> Result<Collide, Error> {
+        let kind = GameData::try_from(self.desc.code[self.context.pointer]).unwrap();
+        self.context.pointer += 1;
+        let args = (0..kind.get_args_count()).map(|_| self.read_f32_unchecked()).collect::<Result<_, _>>()?;
+        Ok(Collide {
+            kind,
+            args,
+        })
+    }
+
+    #[inline]
+    fn read_f32(&mut self) -> Result<Option<f32>, Error> {
+        let src = self.desc.code[self.context.pointer];
+        match src {
+            0 => {
+                let data = self.desc.code[self.context.pointer + 1..self.context.pointer + 5].try_into().unwrap();
+                self.context.pointer += 5;
+                Ok(Some(f32::from_be_bytes(data)))
+            }
+            1 => {
+                let data = self.desc.code[self.context.pointer + 1];
+                self.context.pointer += 2;
+                Ok(Some(self.read_game(data)))
+            }
+            2 => {
+                let data = self.desc.code[self.context.pointer + 1];
+                self.context.pointer += 2;
+                Ok(Some(self.data[data as usize].get()))
+            }
+            3 => {
+                let data = self.desc.code[self.context.pointer + 1];
+                self.context.pointer += 2;
+                Ok(Some(self.context.var_stack[data as usize]))
+            }
+            4 => {
+                self.context.pointer += 1;
+                self.pop().map(Some)
+            }
+            9 => {
+                self.context.pointer += 1;
+                Ok(None)
+            }
+            _ => Err(self.error(format!("unknown data src {}", src)))
+        }
+    }
+}
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+    use std::io::Error;
+
+    use crate::debugger::ScriptDebug;
+    use crate::game_data::{GameData, ItemKind};
+    use crate::manager::ScriptManager;
+    use crate::pool_script::Parser;
+    use crate::random::Random;
+    use crate::vm::{ScriptCommand, ScriptContext, ScriptHost};
+
+    #[derive(Default)]
+    struct TestHost {
+        random: Random,
+        commands: Vec<ScriptCommand>,
+        errors: Vec<String>,
+    }
+
+    impl ScriptHost for TestHost {
+        fn get(&self, _: GameData) -> f32 {
+            0.0
+        }
+
+        fn set(&mut self, _: GameData, _: f32) {}
+
+        fn submit(&mut self, command: ScriptCommand) {
+            self.commands.push(command);
+        }
+
+        fn random(&mut self) -> &mut Random {
+            &mut self.random
+        }
+
+        fn error(&mut self, error: Error) {
+            self.errors.push(error.to_string());
+            self.submit(ScriptCommand::Kill);
+        }
+    }
+
+    fn load(source: &str) -> (ScriptManager, ScriptContext) {
+        let mut script_manager = ScriptManager::default();
+        let bin = Parser::new(source.as_bytes()).try_parse().unwrap();
+        let index = script_manager.insert("test", bin);
+        let context = ScriptContext::new(&script_manager.scripts[index], vec![]);
+        (script_manager, context)
+    }
+
+    #[test]
+    fn kill_runaway_loop() {
+        let (mut script_manager, mut context) = load("function tick
+    move_up 1
+    loop
+        let a = 1
+    end
+end
+");
+        script_manager.budget = 100;
+        let mut host = TestHost::default();
+        context.tick_function(&mut host, &script_manager, None);
+        assert_eq!(host.errors, vec!["[run script]test::tick at 0016: ran more than 100 commands, is there a loop without wait?"]);
+        assert!(matches!(host.commands.as_slice(), [ScriptCommand::Move(_), ScriptCommand::Kill]));
+
+        //the next tick starts again with the full budget
+        context.tick_function(&mut host, &script_manager, None);
+        assert_eq!(host.errors.len(), 2);
+        assert!(matches!(host.commands[2], ScriptCommand::Move(_)));
+    }
+
+    #[test]
+    fn stop_deep_calls() {
+        let (script_manager, mut context) = load("function tick
+    let a = call deep(1)
+end
+
+function deep(x)
+    return call deep(x + 1) + 1
+end
+");
+        let mut host = TestHost::default();
+        context.tick_function(&mut host, &script_manager, None);
+        assert_eq!(host.errors, vec!["[run script]test::deep at 0010: calls deep nested more than 64 times"]);
+        assert!(matches!(host.commands.as_slice(), [ScriptCommand::Kill]));
+    }
+
+    #[test]
+    fn reject_bad_string_index() {
+        let source = "function tick
+    let str tex = \"bullet\"
+    summon_b (tex) 0 0 0 1 90 circle 1 normal
+end
+";
+        for (index, error) in [(-1.0f32, "no string -1"), (0.5, "no string 0.5"), (1.0, "no string 1")] {
+            let mut bin = Parser::new(source.as_bytes()).try_parse().unwrap();
+            //the const pushed to the local is the index of the string
+            bin.functions.get_mut("tick").unwrap().code[2..6].copy_from_slice(&index.to_be_bytes());
+            let mut script_manager = ScriptManager::default();
+            let script = script_manager.insert("test", bin);
+            let mut context = ScriptContext::new(&script_manager.scripts[script], vec![]);
+            let mut host = TestHost::default();
+            context.tick_function(&mut host, &script_manager, None);
+            assert_eq!(host.errors, vec![format!("[run script]test::tick at 0009: {}", error)]);
+        }
+    }
+
+    #[test]
+    fn migrate_to_more_data() {
+        let dir = std::env::temp_dir().join(format!("pool_script_migrate_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("reloaded.pthps");
+        std::fs::write(&path, "data\n    f32 a\nend\nfunction tick\n    let a = a + 1\nend\n").unwrap();
+        let mut script_manager = ScriptManager::default();
+        let (index, _) = script_manager.reload_source(&path).unwrap();
+        let mut context = ScriptContext::new(&script_manager.scripts[index], vec![]);
+        let mut host = TestHost::default();
+        context.tick_function(&mut host, &script_manager, None);
+
+        //the live context writes to the new slots after it is migrated
+        std::fs::write(&path, "data\n    f32 b\n    f32 c\n    f32 a\nend\nfunction tick\n    let a = a + 1\n    let c = 5\nend\n").unwrap();
+        let (new_index, old) = script_manager.reload_source(&path).unwrap();
+        assert_eq!(index, new_index);
+        context.migrate(&old.unwrap(), &script_manager.scripts[index]);
+        context.tick_function(&mut host, &script_manager, None);
+        assert!(host.errors.is_empty());
+        assert_eq!(Some(2.0), context.get_data(&script_manager, "a"));
+        assert_eq!(Some(5.0), context.get_data(&script_manager, "c"));
+        assert_eq!(Some(0.0), context.get_data(&script_manager, "b"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn step_through_expression() {
+        let (script_manager, mut context) = load("data
+    f32 a
+end
+function tick
+    let a = 1 + 2 * 3
+    wait 100
+end
+");
+        context.debug = Some(Box::new(ScriptDebug::new(0, HashSet::new())));
+        let mut host = TestHost::default();
+        for _ in 0..5 {
+            context.tick_function(&mut host, &script_manager, None);
+            context.debug.as_mut().unwrap().step();
+        }
+        assert!(host.errors.is_empty());
+        assert_eq!(Some(7.0), context.get_data(&script_manager, "a"));
+    }
+
+    #[test]
+    fn break_inside_expression() {
+        let (script_manager, mut context) = load("data
+    f32 a
+    f32 b
+end
+function tick
+    let a = a + 2 * 3
+    let b = b + 1
+end
+");
+        //9 is the add of the first statement
+        let mut debug = ScriptDebug::new(0, HashSet::from([9]));
+        debug.resume();
+        context.debug = Some(Box::new(debug));
+        let mut host = TestHost::default();
+        context.tick_function(&mut host, &script_manager, None);
+        //paused at the second statement
+        assert_eq!(Some(6.0), context.get_data(&script_manager, "a"));
+        assert_eq!(Some(0.0), context.get_data(&script_manager, "b"));
+
+        context.debug.as_mut().unwrap().resume();
+        context.tick_function(&mut host, &script_manager, None);
+        assert!(host.errors.is_empty());
+        assert_eq!(Some(6.0), context.get_data(&script_manager, "a"));
+        assert_eq!(Some(1.0), context.get_data(&script_manager, "b"));
+    }
+
+    #[test]
+    fn summon_unknown_script() {
+        let (script_manager, mut context) = load("function tick
+    let str ai = \"missing\"
+    summon_b bullet 0 0 0 1 90 circle 1 (ai) 2
+    summon_e bullet 0 0 0 10 circle 1 (ai)
+end
+");
+        let mut host = TestHost::default();
+        context.tick_function(&mut host, &script_manager, None);
+        assert!(host.errors.is_empty());
+        assert!(matches!(host.commands.as_slice(),
+            [ScriptCommand::SummonBullet(.., ai, args), ScriptCommand::SummonEnemy(..)] if ai == "missing" && args == &[2.0]));
+    }
+
+    #[test]
+    fn drop_items() {
+        let (script_manager, mut context) = load("function tick
+    let a = 2
+    drop_item point a + 1
+    drop_item full_power 1
+end
+");
+        let mut host = TestHost::default();
+        context.tick_function(&mut host, &script_manager, None);
+        assert!(host.errors.is_empty());
+        assert!(matches!(host.commands.as_slice(),
+            [ScriptCommand::DropItem(ItemKind::Point, count), ScriptCommand::DropItem(ItemKind::FullPower, _)] if *count == 3.0));
+    }
+}
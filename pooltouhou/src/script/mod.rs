@@ -1,105 +1,51 @@
-use std::collections::{HashMap, VecDeque};
-use std::fs::File;
-use std::io::BufReader;
-use std::path::PathBuf;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
 
-use pool_script::pool_script::FunctionDesc;
-use pool_script::PoolScriptBin;
+use pool_script::game_data::{GameData, ItemKind};
+use pool_script::random::Random;
+use pool_script::vm::{Collide, ScriptCommand, ScriptHost};
 use pthapi::{CollideType, GamePos};
 
-pub mod script_context;
+pub use pool_script::manager::{ScriptDesc, ScriptManager, script_dir};
+pub use pool_script::vm::ScriptContext;
 
-pub const ON_DIE_FUNCTION: &str = "on_die";
-
-#[derive(Debug, Clone)]
-pub struct ScriptDesc {
-    pub name: String,
-    version: u32,
-    data_count: u8,
-    index: usize,
-    pub functions: HashMap<String, FunctionDesc>,
-    pub tick_function: Option<FunctionDesc>,
-}
+pub mod watcher;
 
-#[derive(Debug, Default)]
-pub struct ScriptManager {
-    pub scripts: Vec<ScriptDesc>,
-    pub script_map: HashMap<String, usize>,
+pub const ON_DIE_FUNCTION: &str = "on_die";
+pub const ON_SPAWN_FUNCTION: &str = "on_spawn";
+/// called with the damage before the hp is reduced
+pub const ON_HIT_FUNCTION: &str = "on_hit";
+pub const ON_LEAVE_SCREEN_FUNCTION: &str = "on_leave_screen";
+pub const ON_GRAZE_FUNCTION: &str = "on_graze";
+/// called when the age reaches the timeout data
+pub const ON_TIMEOUT_FUNCTION: &str = "on_timeout";
+/// The data holding the ticks the object could live, no timeout if it is not positive
+pub const TIMEOUT_DATA: &str = "timeout";
+/// The data of the bomb script holding the radius of its area
+pub const BOMB_RADIUS_DATA: &str = "radius";
+/// The data of the bomb script holding the damage to the enemies in its area in a tick
+pub const BOMB_DAMAGE_DATA: &str = "damage";
+
+/// What the game does after `on_die` by the returned value
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DieEffect {
+    /// nothing or 0 is returned
+    Remove,
+    /// 1: remove it and clear the enemy bullets
+    ClearBullets,
+    /// 2: keep it alive with the hp it is summoned with, for the next phase
+    Revive,
 }
 
-impl ScriptManager {
-    pub fn get_script_data_count(&self, name: &str) -> u8 {
-        if let Some(index) = self.script_map.get(name) {
-            self.scripts[*index].data_count
-        } else {
-            panic!("There is no script with name:  {}", name)
-        }
-    }
-
-    pub fn load_script_data_count(&mut self, name: &str) -> u8 {
-        if let Some(index) = self.script_map.get(name) {
-            self.scripts[*index].data_count
-        } else if let Some(script) = self.load_script(name) {
-            script.data_count
-        } else {
-            panic!("There is no script with name:  {}", name)
-        }
-    }
-
-    pub fn get_script(&self, name: &str) -> Option<&ScriptDesc> {
-        if let Some(index) = self.script_map.get(name) {
-            self.scripts.get(*index)
-        } else {
-            None
-        }
-    }
-
-    pub(crate) fn load_script(&mut self, name: &str) -> Option<&ScriptDesc> {
-        println!("loading script: {}", name);
-        let path = PathBuf::from(std::env::current_dir().unwrap().to_str().unwrap().to_owned() + "/script/" + name + ".pthpsb");
-        if let Ok(file) = File::open(&path) {
-            let mut bin = PoolScriptBin::try_parse_bin(BufReader::new(file)).ok()?;
-            let index = self.scripts.len();
-            let tick_function = bin.functions.remove("tick");
-            let script = ScriptDesc {
-                name: name.into(),
-                version: bin.version,
-                index,
-                functions: bin.functions,
-                tick_function,
-                data_count: bin.data.len() as _,
-            };
-            log::info!("Loaded script {} with data count {} and tick max stack {}", name,
-                script.data_count, script.tick_function.as_ref().map(|x| x.max_stack).unwrap_or(u16::MAX));
-            self.scripts.push(script);
-            self.script_map.insert(name.into(), index);
-            return self.scripts.get(index);
-        } else {
-            eprintln!("Script not found in {:?}", path);
-        }
-        None
-    }
-
-    pub fn load_scripts(&mut self) {
-        self.scripts.clear();
-        self.script_map.clear();
-        let path = PathBuf::from(std::env::current_dir().unwrap().to_str().unwrap().to_owned() + "/script/");
-        let dir = path.read_dir().unwrap();
-        for file in dir {
-            match file {
-                Ok(entry) => {
-                    if let Ok(file_type) = entry.file_type() {
-                        if file_type.is_file() && entry.file_name().to_str().to_owned().unwrap().ends_with(".pthpsb") {
-                            let name = &entry.file_name().into_string().unwrap().replace(".pthpsb", "");
-                            if !self.script_map.contains_key(name) {
-                                self.load_script(name);
-                            }
-                        }
-                    }
-                }
-                Err(err) => {
-                    eprintln!("read entry failed! {}", err);
-                }
+impl From<Option<f32>> for DieEffect {
+    fn from(value: Option<f32>) -> Self {
+        match value.unwrap_or(0.0) as i32 {
+            1 => DieEffect::ClearBullets,
+            2 => DieEffect::Revive,
+            0 => DieEffect::Remove,
+            x => {
+                log::warn!("Unknown die effect {}", x);
+                DieEffect::Remove
             }
         }
     }
@@ -122,50 +68,140 @@ pub enum ScriptGameCommand {
     SummonEnemy(String, f32, f32, f32, f32, CollideType, String, Vec<f32>),
     SummonBullet(String, f32, f32, f32, f32, f32, CollideType, String, Vec<f32>),
     Kill,
+    /// the kind, x, y and the count of the items
+    DropItem(ItemKind, f32, f32, u32),
 }
 
-#[derive(Debug, Clone)]
-pub struct CalcStack {
-    data: Vec<f32>,
-    pub last_idx: isize,
+impl ScriptGameCommand {
+    /// The items drop at the position of the object
+    fn new(command: ScriptCommand, pos: GamePos) -> Self {
+        match command {
+            ScriptCommand::Move(v) => ScriptGameCommand::Move(v),
+            ScriptCommand::SummonEnemy(name, x, y, z, hp, collide, script, args) =>
+                ScriptGameCommand::SummonEnemy(name, x, y, z, hp, collide_type(collide), script, args),
+            ScriptCommand::SummonBullet(name, x, y, z, scale, angle, collide, script, args) =>
+                ScriptGameCommand::SummonBullet(name, x, y, z, scale, angle, collide_type(collide), script, args),
+            ScriptCommand::Kill => ScriptGameCommand::Kill,
+            ScriptCommand::DropItem(kind, count) => ScriptGameCommand::DropItem(kind, pos.x, pos.y, count as u32),
+        }
+    }
+}
+
+fn collide_type(collide: Collide) -> CollideType {
+    CollideType::try_from((collide.kind as u8, collide.args)).unwrap()
 }
 
-impl Default for CalcStack {
-    fn default() -> Self {
-        let mut data = Vec::with_capacity(64);
-        data.resize(data.capacity(), 0.0);
+#[derive(Debug, Clone, Default)]
+pub struct ScriptGameData {
+    pub player_tran: GamePos,
+    pub(crate) submit_command: VecDeque<ScriptGameCommand>,
+    /// only the functions not thread safe could use it
+    pub random: Random,
+    /// the ticks since the stage starts
+    pub tick: u32,
+    pub difficulty: u8,
+    pub enemy_count: u32,
+    pub bullet_count: u32,
+    pub player_lives: u8,
+    pub player_bombs: u8,
+    pub player_power: f32,
+    pub player_focused: bool,
+    pub player_graze: u32,
+}
+
+impl ScriptGameData {
+    /// The game state for the parallel pass without the commands and the random
+    pub fn snapshot(&self) -> Self {
         Self {
-            data,
-            last_idx: -1,
+            player_tran: self.player_tran,
+            submit_command: Default::default(),
+            random: Default::default(),
+            tick: self.tick,
+            difficulty: self.difficulty,
+            enemy_count: self.enemy_count,
+            bullet_count: self.bullet_count,
+            player_lives: self.player_lives,
+            player_bombs: self.player_bombs,
+            player_power: self.player_power,
+            player_focused: self.player_focused,
+            player_graze: self.player_graze,
         }
     }
-}
 
-impl CalcStack {
-    pub unsafe fn pop(&mut self) -> f32 {
-        let last = *self.data.get_unchecked(self.last_idx as usize);
-        self.last_idx -= 1;
-        last
+    /// The host of the script run by the object
+    pub fn host<'a, 'b>(&'a mut self, object: &'a mut TempGameContext<'b>) -> GameHost<'a, 'b> {
+        GameHost {
+            game: self,
+            object,
+        }
     }
+}
+
+/// The object running the script
+#[derive(Debug, Default)]
+pub struct TempGameContext<'a> {
+    pub(crate) tran: Option<&'a mut GamePos>,
+    pub(crate) hp: f32,
+    /// the facing angle in degrees
+    pub(crate) angle: f32,
+    /// the distance moved in the last tick
+    pub(crate) speed: f32,
+    /// the ticks since the object is summoned
+    pub(crate) age: u32,
+}
 
-    pub fn push(&mut self, v: f32) {
-        if self.last_idx >= (self.data.len() - 1) as isize {
-            panic!("Overflow!");
+/// The game state seen by the script of an object
+pub struct GameHost<'a, 'b> {
+    game: &'a mut ScriptGameData,
+    object: &'a mut TempGameContext<'b>,
+}
+
+impl ScriptHost for GameHost<'_, '_> {
+    fn get(&self, data: GameData) -> f32 {
+        let game = &*self.game;
+        let object = &*self.object;
+        match data {
+            GameData::PosX => object.tran.as_ref().unwrap().x,
+            GameData::PosY => object.tran.as_ref().unwrap().y,
+            GameData::PosZ => object.tran.as_ref().unwrap().z,
+            GameData::PlayerX => game.player_tran.x,
+            GameData::PlayerY => game.player_tran.y,
+            GameData::PlayerZ => game.player_tran.z,
+            GameData::Tick => game.tick as f32,
+            GameData::Hp => object.hp,
+            GameData::Angle => object.angle,
+            GameData::Speed => object.speed,
+            GameData::Age => object.age as f32,
+            GameData::Difficulty => game.difficulty as f32,
+            GameData::EnemyCount => game.enemy_count as f32,
+            GameData::BulletCount => game.bullet_count as f32,
+            GameData::PlayerLives => game.player_lives as f32,
+            GameData::PlayerBombs => game.player_bombs as f32,
+            GameData::PlayerPower => game.player_power,
+            GameData::PlayerFocused => if game.player_focused { 1.0 } else { 0.0 },
+            GameData::PlayerGraze => game.player_graze as f32,
+            GameData::CircleCollide => unreachable!("The collide is not a value")
         }
-        unsafe {
-            self.last_idx += 1;
-            *self.data.get_unchecked_mut(self.last_idx as usize) = v;
+    }
+
+    fn set(&mut self, data: GameData, value: f32) {
+        match data {
+            GameData::PosX => self.object.tran.as_mut().unwrap().x = value,
+            GameData::PosY => self.object.tran.as_mut().unwrap().y = value,
+            GameData::PosZ => self.object.tran.as_mut().unwrap().z = value,
+            GameData::PlayerX => self.game.player_tran.x = value,
+            GameData::PlayerY => self.game.player_tran.y = value,
+            GameData::PlayerZ => self.game.player_tran.z = value,
+            _ => unreachable!("{} is read only", data.name())
         }
     }
 
-    pub unsafe fn last_mut(&mut self) -> &mut f32 {
-        self.data.get_unchecked_mut(self.last_idx as usize)
+    fn submit(&mut self, command: ScriptCommand) {
+        let pos = self.object.tran.as_deref().copied().unwrap_or_default();
+        self.game.submit_command.push_back(ScriptGameCommand::new(command, pos));
     }
-}
 
-#[derive(Debug, Clone, Default)]
-pub struct ScriptGameData {
-    pub player_tran: GamePos,
-    pub(crate) submit_command: VecDeque<ScriptGameCommand>,
-    pub calc_stack: CalcStack,
-}
\ No newline at end of file
+    fn random(&mut self) -> &mut Random {
+        &mut self.game.random
+    }
+}
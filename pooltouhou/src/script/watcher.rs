@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The extension of the script sources
+pub const SOURCE_EXTENSION: &str = "pthps";
+
+/// Polls the modified time of the script sources in the directory
+#[derive(Debug, Default)]
+pub struct ScriptWatcher {
+    dir: PathBuf,
+    modified: HashMap<PathBuf, SystemTime>,
+}
+
+impl ScriptWatcher {
+    /// The sources existing now are not reported by the first poll
+    pub fn new(dir: PathBuf) -> Self {
+        let mut watcher = Self {
+            dir,
+            modified: HashMap::new(),
+        };
+        watcher.poll();
+        watcher
+    }
+
+    /// The sources created or modified since the last poll
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let dir = match self.dir.read_dir() {
+            Ok(dir) => dir,
+            Err(e) => {
+                log::warn!("Watch script dir {:?} failed: {}", self.dir, e);
+                return vec![];
+            }
+        };
+        let mut changed = vec![];
+        for entry in dir.flatten() {
+            let path = entry.path();
+            if !is_source(&path) {
+                continue;
+            }
+            let modified = match entry.metadata().and_then(|x| x.modified()) {
+                Ok(modified) => modified,
+                Err(e) => {
+                    log::warn!("Read modified time of {:?} failed: {}", path, e);
+                    continue;
+                }
+            };
+            if self.modified.insert(path.clone(), modified) != Some(modified) {
+                changed.push(path);
+            }
+        }
+        changed
+    }
+}
+
+pub fn is_source(path: &Path) -> bool {
+    path.is_file() && path.extension() == Some(OsStr::new(SOURCE_EXTENSION))
+}
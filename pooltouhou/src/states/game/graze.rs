@@ -0,0 +1,51 @@
+use pthapi::{CollideType, GamePos, Player};
+
+/// The score of grazing a bullet
+pub const GRAZE_SCORE: u64 = 500;
+
+/// Count the bullet once when it comes in the graze radius of the alive player, return true if it is counted
+pub fn graze(player: &mut Player, grazed: &mut bool, collide: CollideType, pos: &GamePos) -> bool {
+    let area = CollideType::Circle {
+        radius: player.graze_radius,
+        radius_2: player.graze_radius * player.graze_radius,
+    };
+    if player.death != 0 || *grazed || !collide.is_collide_with(pos, &area, &player.pos) {
+        return false;
+    }
+    *grazed = true;
+    player.graze += 1;
+    player.score += GRAZE_SCORE;
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use pthapi::{CollideType, Player, SimpleEnemyBullet};
+
+    use crate::states::game::graze::{graze, GRAZE_SCORE};
+
+    #[test]
+    fn graze_simple_bullet_once() {
+        let mut player = Player::default();
+        let collide = CollideType::Circle { radius: 2.0, radius_2: 4.0 };
+        //passes the player 20 above it, in the graze radius but not on the player
+        let mut bullet = SimpleEnemyBullet::new((-200.0, player.pos.y + 20.0, 0.0).into(), 0, collide, 5.0, 0.0);
+        let mut counted = 0;
+        for _ in 0..80 {
+            bullet.tick();
+            if graze(&mut player, &mut bullet.grazed, bullet.collide, &bullet.pos) {
+                counted += 1;
+            }
+        }
+        assert!(bullet.pos.x > player.pos.x + player.graze_radius);
+        assert_eq!(1, counted);
+        assert_eq!(1, player.graze);
+        assert_eq!(GRAZE_SCORE, player.score);
+
+        //the dead player grazes nothing
+        player.death = -1;
+        let mut bullet = SimpleEnemyBullet::new(player.pos, 0, collide, 0.0, 0.0);
+        assert!(!graze(&mut player, &mut bullet.grazed, bullet.collide, &bullet.pos));
+        assert!(!bullet.grazed);
+    }
+}
@@ -0,0 +1,163 @@
+use pool_script::game_data::ItemKind;
+use pthapi::{GAME_MIN_Y, GamePos, Player};
+
+/// All the items fly to the player above it
+pub const COLLECT_LINE: f32 = 200.0;
+/// The focused player pulls the items in it
+pub const MAGNET_RADIUS: f32 = 120.0;
+/// The player gets the items in it
+pub const COLLECT_RADIUS: f32 = 40.0;
+pub const FLY_SPEED: f32 = 16.0;
+pub const FALL_SPEED: f32 = 4.0;
+pub const GRAVITY: f32 = 0.15;
+/// The items rise at the speed when dropped
+pub const DROP_SPEED: f32 = -4.0;
+pub const MAX_POWER: f32 = 4.0;
+pub const POWER_ITEM: f32 = 0.05;
+pub const MAX_LIVES: u8 = 8;
+pub const MAX_BOMBS: u8 = 8;
+/// The point item is worth it at the collect line or above
+pub const MAX_POINT: u64 = 10000;
+/// The point item is worth it at the bottom
+pub const MIN_POINT: u64 = 1000;
+/// The score of the other items
+pub const ITEM_SCORE: u64 = 10;
+/// The score of the power item at the max power
+pub const FULL_POWER_SCORE: u64 = 1000;
+
+pub struct Item {
+    pub pos: GamePos,
+    pub kind: ItemKind,
+    /// the falling speed, it rises if negative
+    pub speed: f32,
+    /// it flies to the player
+    pub collecting: bool,
+}
+
+/// A dropped item rises a little and falls
+pub fn drop_item(kind: ItemKind, pos: GamePos) -> Item {
+    Item {
+        pos,
+        kind,
+        speed: DROP_SPEED,
+        collecting: false,
+    }
+}
+
+/// The higher the player is, the more the point item is worth
+pub fn point_value(y: f32) -> u64 {
+    let rate = ((y - GAME_MIN_Y) / (COLLECT_LINE - GAME_MIN_Y)).clamp(0.0, 1.0);
+    MIN_POINT + ((MAX_POINT - MIN_POINT) as f32 * rate) as u64
+}
+
+pub fn collect(player: &mut Player, kind: ItemKind) {
+    match kind {
+        ItemKind::Power if player.power >= MAX_POWER => player.score += FULL_POWER_SCORE,
+        ItemKind::Power => {
+            //rounded so the power reaches the levels of the shots
+            player.power = (((player.power + POWER_ITEM) * 100.0).round() / 100.0).min(MAX_POWER);
+            player.score += ITEM_SCORE;
+        }
+        ItemKind::Point => player.score += point_value(player.pos.y),
+        ItemKind::Bomb => {
+            player.bombs = (player.bombs + 1).min(MAX_BOMBS);
+            player.score += ITEM_SCORE;
+        }
+        ItemKind::Life => {
+            player.lives = (player.lives + 1).min(MAX_LIVES);
+            player.score += ITEM_SCORE;
+        }
+        ItemKind::FullPower => {
+            player.power = MAX_POWER;
+            player.score += ITEM_SCORE;
+        }
+    }
+}
+
+/// Move the items and collect the ones reaching the player
+///
+/// The items fly to the player above [`COLLECT_LINE`] or pulled by the focused player, the dead player gets nothing.
+pub fn tick_items(items: &mut Vec<Item>, player: &mut Player) {
+    let alive = player.death == 0;
+    items.retain_mut(|item| {
+        let dx = player.pos.x - item.pos.x;
+        let dy = player.pos.y - item.pos.y;
+        let distance_2 = dx * dx + dy * dy;
+        item.collecting = alive && (item.collecting || player.pos.y >= COLLECT_LINE
+            || (player.walking && distance_2 < MAGNET_RADIUS * MAGNET_RADIUS));
+        if item.collecting {
+            let distance = distance_2.sqrt();
+            if distance > FLY_SPEED {
+                item.pos.x += dx / distance * FLY_SPEED;
+                item.pos.y += dy / distance * FLY_SPEED;
+            } else {
+                item.pos.x = player.pos.x;
+                item.pos.y = player.pos.y;
+            }
+        } else {
+            item.pos.y -= item.speed;
+            item.speed = (item.speed + GRAVITY).min(FALL_SPEED);
+        }
+        let dx = player.pos.x - item.pos.x;
+        let dy = player.pos.y - item.pos.y;
+        if alive && dx * dx + dy * dy < COLLECT_RADIUS * COLLECT_RADIUS {
+            collect(player, item.kind);
+            return false;
+        }
+        item.pos.y > GAME_MIN_Y - 50.0
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use pool_script::game_data::ItemKind;
+    use pthapi::Player;
+
+    use crate::states::game::item::{collect, COLLECT_LINE, drop_item, MAX_POINT, MIN_POINT, point_value, tick_items};
+
+    #[test]
+    fn point_by_height() {
+        assert_eq!(MAX_POINT, point_value(COLLECT_LINE));
+        assert_eq!(MAX_POINT, point_value(400.0));
+        assert_eq!(MIN_POINT, point_value(-450.0));
+        assert!(point_value(-100.0) > point_value(-200.0));
+    }
+
+    #[test]
+    fn power_levels() {
+        let mut player = Player::default();
+        for _ in 0..20 {
+            collect(&mut player, ItemKind::Power);
+        }
+        assert_eq!(2.0, player.power);
+        collect(&mut player, ItemKind::FullPower);
+        let score = player.score;
+        collect(&mut player, ItemKind::Power);
+        assert_eq!(4.0, player.power);
+        assert!(player.score > score);
+    }
+
+    #[test]
+    fn collect_above_line() {
+        let mut player = Player::default();
+        let mut items = vec![drop_item(ItemKind::Point, (300.0, 0.0, 0.0).into())];
+        tick_items(&mut items, &mut player);
+        assert!(!items[0].collecting);
+
+        player.pos.y = COLLECT_LINE;
+        for _ in 0..100 {
+            tick_items(&mut items, &mut player);
+        }
+        assert!(items.is_empty());
+        assert_eq!(MAX_POINT, player.score);
+
+        //the dead player does not pull the items
+        player.death = -1;
+        let mut items = vec![drop_item(ItemKind::Life, (0.0, 0.0, 0.0).into())];
+        for _ in 0..1000 {
+            tick_items(&mut items, &mut player);
+        }
+        assert!(items.is_empty());
+        assert_eq!(3, player.lives);
+    }
+}
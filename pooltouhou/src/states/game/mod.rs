@@ -4,25 +4,51 @@ use rayon::iter::{IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelE
 use wgpu_glyph::{HorizontalAlign, Layout, VerticalAlign};
 use winit::event::VirtualKeyCode;
 
+use pool_script::debugger::ScriptDebug;
+use pool_script::random::Random;
 use pth_render_lib::*;
-use pthapi::{CollideType, GAME_MAX_X, GAME_MAX_Y, GAME_MIN_X, GAME_MIN_Y, GamePos, Player, PlayerBullet, Rotation, SimpleEnemyBullet, TexHandle};
+use pthapi::{CollideType, GAME_MAX_X, GAME_MAX_Y, GAME_MIN_X, GAME_MIN_Y, GamePos, Player, PlayerBullet, Rotation, ShotKind, SimpleEnemyBullet, TexHandle};
 
 use crate::handles::{CounterProgress, Progress};
 use crate::LoopState;
 use crate::render::texture2d::Texture2DObject;
-use crate::script::{ON_DIE_FUNCTION, ScriptGameCommand, ScriptGameData, ScriptManager};
-use crate::script::script_context::{ScriptContext, TempGameContext};
+use crate::script::{DieEffect, ON_DIE_FUNCTION, ON_GRAZE_FUNCTION, ON_HIT_FUNCTION, ON_LEAVE_SCREEN_FUNCTION, ON_SPAWN_FUNCTION, ON_TIMEOUT_FUNCTION, BOMB_DAMAGE_DATA, BOMB_RADIUS_DATA, ScriptContext, ScriptGameCommand, ScriptGameData, ScriptManager, TempGameContext, TIMEOUT_DATA};
+use crate::script::script_dir;
+use crate::script::watcher::ScriptWatcher;
 use crate::states::{GameState, StateData, Trans};
+use crate::states::game::item::Item;
+use crate::states::game::shot::ShotType;
 
 pub mod anime;
+pub mod graze;
+pub mod item;
+pub mod shot;
+
+/// The ticks the hit player could bomb in to cancel the death
+const DEATH_BOMB_TICKS: isize = 8;
+/// The ticks from the death to the respawn
+const RESPAWN_TICKS: isize = 60;
+/// The ticks the player could not be hit after the respawn or a bomb
+const INVULNERABLE_TICKS: u32 = 180;
+/// Where the player starts and respawns
+const PLAYER_SPAWN: (f32, f32) = (0.0, -100.0);
+/// The bombs the player has at least after the respawn
+const RESPAWN_BOMBS: u8 = 3;
 
 pub struct Enemy {
     pub pos: GamePos,
     pub hp: f32,
+    /// the hp it is summoned with
+    pub max_hp: f32,
     pub collide: CollideType,
     pub script: ScriptContext,
     pub tex: TexHandle,
     pub id: u64,
+    /// the ticks since it is summoned
+    pub age: u32,
+    pub died: bool,
+    /// it was in the screen in the last tick
+    pub on_screen: bool,
 }
 
 pub struct EnemyBullet {
@@ -34,6 +60,23 @@ pub struct EnemyBullet {
     pub script: ScriptContext,
     pub died: bool,
     pub id: u64,
+    /// the distance moved in the last tick
+    pub speed: f32,
+    /// the distance moved in this tick
+    pub moved: f32,
+    /// the ticks since it is summoned
+    pub age: u32,
+    pub grazed: bool,
+}
+
+/// The bomb of the player, its script keeps the radius, the damage in a tick and the timeout in the data
+pub struct Bomb {
+    pub pos: GamePos,
+    pub radius: f32,
+    pub script: ScriptContext,
+    /// the ticks since it is used
+    pub age: u32,
+    pub died: bool,
 }
 
 impl Enemy {
@@ -41,14 +84,130 @@ impl Enemy {
         Self {
             pos,
             hp,
+            max_hp: hp,
             collide,
             script,
             tex,
             id,
+            age: 0,
+            died: false,
+            on_screen: false,
+        }
+    }
+
+    /// Run the callback with the state of the enemy
+    fn callback(&mut self, name: &str, args: &[f32], game_data: &mut ScriptGameData, script_manager: &ScriptManager,
+                sender: &Sender<ScriptGameCommand>) -> CallbackResult {
+        let mut temp = TempGameContext {
+            tran: Some(&mut self.pos),
+            hp: self.hp,
+            age: self.age,
+            ..Default::default()
+        };
+        let result = run_callback(&mut self.script, name, args, &mut temp, game_data, script_manager, sender);
+        self.died |= result.killed;
+        result
+    }
+
+    /// The enemy dies unless `on_timeout` extends the timeout
+    fn check_timeout(&mut self, game_data: &mut ScriptGameData, script_manager: &ScriptManager, sender: &Sender<ScriptGameCommand>) {
+        if is_timeout(&self.script, self.age, script_manager) {
+            self.callback(ON_TIMEOUT_FUNCTION, &[], game_data, script_manager, sender);
+            self.died |= is_timeout(&self.script, self.age, script_manager);
+        }
+    }
+}
+
+impl EnemyBullet {
+    fn move_up(&mut self, v: f32) {
+        self.pos.x += self.rot.facing_x * v;
+        self.pos.y += self.rot.facing_y * v;
+        self.moved += v;
+    }
+
+    /// Run the callback with the state of the bullet
+    fn callback(&mut self, name: &str, args: &[f32], game_data: &mut ScriptGameData, script_manager: &ScriptManager,
+                sender: &Sender<ScriptGameCommand>) -> CallbackResult {
+        let mut temp = TempGameContext {
+            tran: Some(&mut self.pos),
+            angle: self.rot.angle,
+            speed: self.speed,
+            age: self.age,
+            ..Default::default()
+        };
+        let result = run_callback(&mut self.script, name, args, &mut temp, game_data, script_manager, sender);
+        self.move_up(result.moved);
+        self.died |= result.killed;
+        result
+    }
+
+    /// The bullet dies unless `on_timeout` extends the timeout
+    fn check_timeout(&mut self, game_data: &mut ScriptGameData, script_manager: &ScriptManager, sender: &Sender<ScriptGameCommand>) {
+        if is_timeout(&self.script, self.age, script_manager) {
+            self.callback(ON_TIMEOUT_FUNCTION, &[], game_data, script_manager, sender);
+            self.died |= is_timeout(&self.script, self.age, script_manager);
         }
     }
 }
 
+impl Bomb {
+    /// Run the tick function, the bomb moves up and dies by `kill` or the timeout
+    fn tick(&mut self, game_data: &mut ScriptGameData, script_manager: &ScriptManager, sender: &Sender<ScriptGameCommand>) {
+        self.age += 1;
+        let mut temp = TempGameContext {
+            tran: Some(&mut self.pos),
+            angle: 90.0,
+            age: self.age,
+            ..Default::default()
+        };
+        self.script.tick_function(&mut game_data.host(&mut temp), script_manager, None);
+        while let Some(x) = game_data.submit_command.pop_front() {
+            match x {
+                ScriptGameCommand::Move(v) => self.pos.y += v,
+                ScriptGameCommand::Kill => self.died = true,
+                ScriptGameCommand::SummonBullet(..) | ScriptGameCommand::SummonEnemy(..) | ScriptGameCommand::DropItem(..) => sender.send(x).unwrap(),
+            }
+        }
+        self.radius = self.data(BOMB_RADIUS_DATA, script_manager);
+        self.died |= is_timeout(&self.script, self.age, script_manager);
+    }
+
+    /// Zero if the script does not have the data
+    fn data(&self, name: &str, script_manager: &ScriptManager) -> f32 {
+        self.script.get_data(script_manager, name).unwrap_or_default()
+    }
+}
+
+/// The commands submitted by the callback
+#[derive(Debug, Default)]
+struct CallbackResult {
+    value: Option<f32>,
+    moved: f32,
+    killed: bool,
+}
+
+/// Run the callback if the script has it, the summons are sent to the command channel
+fn run_callback(script: &mut ScriptContext, name: &str, args: &[f32], temp: &mut TempGameContext, game_data: &mut ScriptGameData,
+                script_manager: &ScriptManager, sender: &Sender<ScriptGameCommand>) -> CallbackResult {
+    let mut result = CallbackResult {
+        value: script.exe_fn_if_present(name, args, &mut game_data.host(temp), script_manager),
+        ..Default::default()
+    };
+    while let Some(x) = game_data.submit_command.pop_front() {
+        match x {
+            ScriptGameCommand::Move(v) => result.moved += v,
+            ScriptGameCommand::Kill => result.killed = true,
+            ScriptGameCommand::SummonBullet(..) | ScriptGameCommand::SummonEnemy(..) | ScriptGameCommand::DropItem(..) => sender.send(x).unwrap(),
+        }
+    }
+    result
+}
+
+fn is_timeout(script: &ScriptContext, age: u32, script_manager: &ScriptManager) -> bool {
+    //no timeout if it is not positive
+    matches!(script.get_data(script_manager, TIMEOUT_DATA), Some(x) if x > 0.0 && age >= x as u32)
+}
+
 
 pub struct Gaming {
     player: Player,
@@ -62,6 +221,26 @@ pub struct Gaming {
     tick: u128,
     obj_id: std::cell::Cell<u64>,
     pausing: bool,
+    /// the seed of the stage random, the same seed gives the same run
+    seed: u64,
+    random: Random,
+    difficulty: u8,
+    script_watcher: ScriptWatcher,
+    /// the object whose script is paused by the debugger
+    debug_object: Option<u64>,
+    /// the ticks the player could not be hit
+    invulnerable: u32,
+    /// the last life is lost, the state pops when the death ends
+    game_over: bool,
+    bomb: Option<Bomb>,
+    /// the script of the bombs, by the config `bomb`
+    bomb_script: String,
+    bomb_tex: TexHandle,
+    items: Vec<Item>,
+    /// by the index of the item kind
+    item_tex: [TexHandle; 5],
+    shot_type: &'static ShotType,
+    player_bullet_tex: TexHandle,
 }
 
 impl Gaming {
@@ -70,6 +249,253 @@ impl Gaming {
         self.obj_id.set(id + 1);
         id
     }
+
+    /// The player touches an enemy or an enemy bullet
+    fn is_player_hit(&self) -> bool {
+        let collide = CollideType::Circle {
+            radius: self.player.radius,
+            radius_2: self.player.radius * self.player.radius,
+        };
+        let pos = &self.player.pos;
+        self.enemy_bullets.iter().any(|x| x.collide.is_collide_with(&x.pos, &collide, pos))
+            || self.simple_bullets.iter().any(|x| x.collide.is_collide_with(&x.pos, &collide, pos))
+            || self.enemies.iter().any(|x| x.collide.is_collide_with(&x.pos, &collide, pos))
+    }
+
+    /// Shoot the streams of the shot type the power allows
+    fn shoot(&mut self) {
+        let pos = self.player.pos;
+        for shot in self.shot_type.shots(self.player.walking, self.player.power) {
+            self.player_bullets.push(PlayerBullet {
+                pos: (pos.x + shot.x, pos.y + shot.y, pos.z).into(),
+                tex: self.player_bullet_tex,
+                damage: shot.damage,
+                speed: shot.speed,
+                rot: Rotation::new(shot.angle),
+                kind: shot.kind,
+            });
+        }
+        self.player.shoot_cooldown = self.shot_type.cooldown;
+    }
+
+    /// Use a bomb at the player, false if the bomb script is not loaded
+    fn bomb(&mut self) -> bool {
+        let script = match self.script_manager.get_script(&self.bomb_script) {
+            Some(script) => script,
+            None => {
+                log::error!("No bomb script {}", self.bomb_script);
+                return false;
+            }
+        };
+        self.bomb = Some(Bomb {
+            pos: self.player.pos,
+            radius: 0.0,
+            script: ScriptContext::new(script, vec![]),
+            age: 0,
+            died: false,
+        });
+        self.player.bombs -= 1;
+        self.invulnerable = INVULNERABLE_TICKS;
+        true
+    }
+
+    /// Clear the enemy bullets and damage the enemies in the area of the bomb
+    fn tick_bomb(&mut self, game_data: &mut ScriptGameData) {
+        let mut bomb = match self.bomb.take() {
+            Some(bomb) => bomb,
+            None => return
+        };
+        bomb.tick(game_data, &self.script_manager, &self.commands.0);
+        let area = CollideType::Circle {
+            radius: bomb.radius,
+            radius_2: bomb.radius * bomb.radius,
+        };
+        self.enemy_bullets.retain(|x| !area.is_collide_with_point(&bomb.pos, &x.pos));
+        self.simple_bullets.retain(|x| !area.is_collide_with_point(&bomb.pos, &x.pos));
+        let damage = bomb.data(BOMB_DAMAGE_DATA, &self.script_manager);
+        let mut idx = 0;
+        while idx < self.enemies.len() {
+            let enemy = &self.enemies[idx];
+            if damage > 0.0 && enemy.hp > 0.0 && enemy.collide.is_collide_with(&enemy.pos, &area, &bomb.pos)
+                && self.damage_enemy(idx, damage, game_data) {
+                continue;
+            }
+            idx += 1;
+        }
+        if !bomb.died {
+            self.bomb = Some(bomb);
+        }
+    }
+
+    /// Hit the enemy with the callbacks, true if it dies and is removed
+    fn damage_enemy(&mut self, idx: usize, damage: f32, game_data: &mut ScriptGameData) -> bool {
+        let enemy = &mut self.enemies[idx];
+        enemy.callback(ON_HIT_FUNCTION, &[damage], game_data, &self.script_manager, &self.commands.0);
+        enemy.hp -= damage;
+        if enemy.died {
+            enemy.hp = 0.0;
+            enemy.died = false;
+        }
+        if enemy.hp > 0.0 {
+            return false;
+        }
+        let result = enemy.callback(ON_DIE_FUNCTION, &[], game_data, &self.script_manager, &self.commands.0);
+        match DieEffect::from(result.value) {
+            DieEffect::Remove => {
+                self.enemies.swap_remove(idx);
+                true
+            }
+            DieEffect::ClearBullets => {
+                self.enemies.swap_remove(idx);
+                self.enemy_bullets.clear();
+                true
+            }
+            DieEffect::Revive => {
+                enemy.hp = enemy.max_hp;
+                enemy.died = false;
+                false
+            }
+        }
+    }
+
+    /// Bomb by the key and step the death of the player, see [`Player::death`]
+    ///
+    /// The hit player could bomb in [`DEATH_BOMB_TICKS`] to live,
+    /// otherwise it loses a life and respawns after [`RESPAWN_TICKS`].
+    /// The state pops when the death with no lives left ends.
+    fn tick_player(&mut self, bomb: bool) -> Trans {
+        match self.player.death {
+            0 => {
+                if bomb && self.player.bombs > 0 && self.bomb.is_none() {
+                    self.bomb();
+                } else if self.invulnerable > 0 {
+                    self.invulnerable -= 1;
+                } else if self.bomb.is_none() && self.is_player_hit() {
+                    self.player.death = DEATH_BOMB_TICKS;
+                }
+            }
+            death if death > 0 => {
+                if bomb && self.player.bombs > 0 && self.bomb() {
+                    self.player.death = 0;
+                    return Trans::None;
+                }
+                self.player.death -= 1;
+                if self.player.death == 0 {
+                    //no bullets are left to hit the player at once after the respawn
+                    self.enemy_bullets.clear();
+                    self.simple_bullets.clear();
+                    if self.player.lives == 0 {
+                        log::info!("Game over at tick {}", self.tick);
+                        self.game_over = true;
+                    } else {
+                        self.player.lives -= 1;
+                    }
+                    self.player.death = -RESPAWN_TICKS;
+                }
+            }
+            _ => {
+                self.player.death += 1;
+                if self.player.death == 0 {
+                    if self.game_over {
+                        return Trans::Pop;
+                    }
+                    self.player.pos.x = PLAYER_SPAWN.0;
+                    self.player.pos.y = PLAYER_SPAWN.1;
+                    self.player.bombs = self.player.bombs.max(RESPAWN_BOMBS);
+                    self.invulnerable = INVULNERABLE_TICKS;
+                }
+            }
+        }
+        Trans::None
+    }
+
+    /// The game state could be read by the scripts, the stage random is moved into it
+    fn script_game_data(&mut self) -> ScriptGameData {
+        ScriptGameData {
+            player_tran: self.player.pos,
+            submit_command: Default::default(),
+            random: std::mem::take(&mut self.random),
+            tick: self.tick as u32,
+            difficulty: self.difficulty,
+            enemy_count: self.enemies.len() as u32,
+            bullet_count: (self.enemy_bullets.len() + self.simple_bullets.len()) as u32,
+            player_lives: self.player.lives,
+            player_bombs: self.player.bombs,
+            player_power: self.player.power,
+            player_focused: self.player.walking,
+            player_graze: self.player.graze,
+        }
+    }
+
+    /// Recompile the changed script sources, the running objects keep the data with the same name
+    fn reload_scripts(&mut self) {
+        for path in self.script_watcher.poll() {
+            log::info!("Reloading script {:?}", path);
+            if let Some((index, Some(old))) = self.script_manager.reload_source(&path) {
+                let new = &self.script_manager.scripts[index];
+                let contexts = self.enemies.iter_mut().map(|x| &mut x.script)
+                    .chain(self.enemy_bullets.iter_mut().map(|x| &mut x.script))
+                    .chain(self.bomb.iter_mut().map(|x| &mut x.script));
+                for context in contexts.filter(|x| x.desc_index == index) {
+                    context.migrate(&old, new);
+                }
+            }
+        }
+    }
+
+    fn object_script(&mut self, id: u64) -> Option<&mut ScriptContext> {
+        self.enemies.iter_mut().find(|x| x.id == id).map(|x| &mut x.script)
+            .or_else(|| self.enemy_bullets.iter_mut().find(|x| x.id == id).map(|x| &mut x.script))
+    }
+
+    /// The object chosen by the config `debug_object` or the nearest one to the player
+    fn choose_debug_object(&self, data: &StateData) -> Option<u64> {
+        if let Some(id) = data.global_state.config.get("debug_object").and_then(|x| x.parse().ok()) {
+            return Some(id);
+        }
+        let distance = |pos: &GamePos| (pos.x - self.player.pos.x).powi(2) + (pos.y - self.player.pos.y).powi(2);
+        self.enemies.iter().map(|x| (x.id, distance(&x.pos)))
+            .chain(self.enemy_bullets.iter().map(|x| (x.id, distance(&x.pos))))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|x| x.0)
+    }
+
+    /// F7 pauses the script of an object or lets it go, F8 runs one statement and F10 runs to the breakpoints
+    /// in the config `script_breakpoints` like `12,40`, a breakpoint inside an expression pauses at the next statement
+    fn debug_script(&mut self, data: &StateData) {
+        if data.inputs.is_pressed(&[VirtualKeyCode::F7]) {
+            if let Some(id) = self.debug_object.take() {
+                if let Some(script) = self.object_script(id) {
+                    script.debug = None;
+                }
+                log::info!("[debug]object {} is not debugged", id);
+            } else if let Some(id) = self.choose_debug_object(data) {
+                let breakpoints = data.global_state.config.get("script_breakpoints")
+                    .map(|x| x.split(',').filter_map(|x| x.trim().parse().ok()).collect())
+                    .unwrap_or_default();
+                if let Some(script) = self.object_script(id) {
+                    script.debug = Some(Box::new(ScriptDebug::new(id, breakpoints)));
+                    self.debug_object = Some(id);
+                }
+            }
+        }
+        let debug = match self.debug_object {
+            Some(id) => self.object_script(id).and_then(|x| x.debug.as_mut()),
+            None => return,
+        };
+        match debug {
+            Some(debug) => {
+                if data.inputs.is_pressed(&[VirtualKeyCode::F8]) {
+                    debug.step();
+                } else if data.inputs.is_pressed(&[VirtualKeyCode::F10]) {
+                    debug.resume();
+                }
+            }
+            None => {
+                log::info!("[debug]object {} is removed", self.debug_object.take().unwrap());
+            }
+        }
+    }
 }
 
 impl Default for Gaming {
@@ -86,37 +512,82 @@ impl Default for Gaming {
             tick: 0,
             obj_id: std::cell::Cell::new(9),
             pausing: false,
+            seed: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)
+                .map(|x| x.as_nanos() as u64).unwrap_or_default(),
+            random: Default::default(),
+            difficulty: 1,
+            script_watcher: Default::default(),
+            debug_object: None,
+            invulnerable: 0,
+            game_over: false,
+            bomb: None,
+            bomb_script: "bomb".to_owned(),
+            bomb_tex: 0,
+            items: vec![],
+            item_tex: [0; 5],
+            shot_type: &shot::SHOT_TYPES[0],
+            player_bullet_tex: 0,
         }
     }
 }
 
 impl GameState for Gaming {
     fn start(&mut self, data: &mut StateData) {
-        log::info!("Gaming state starting");
-        let mut game = ScriptGameData {
-            player_tran: self.player.pos,
-            submit_command: Default::default(),
-            calc_stack: Default::default(),
-        };
-        self.player.pos.y = -100.0;
+        //replay the run by the seed in config
+        if let Some(seed) = data.global_state.config.get("seed").and_then(|x| x.parse().ok()) {
+            self.seed = seed;
+        }
+        if let Some(difficulty) = data.global_state.config.get("difficulty").and_then(|x| x.parse().ok()) {
+            self.difficulty = difficulty;
+        }
+        if let Some(budget) = data.global_state.config.get("script_budget").and_then(|x| x.parse().ok()) {
+            self.script_manager.budget = budget;
+        }
+        if let Some(name) = data.global_state.config.get("bomb") {
+            self.bomb_script = name.clone();
+        }
+        if let Some(name) = data.global_state.config.get("shot_type") {
+            match shot::shot_type(name) {
+                Some(shot_type) => self.shot_type = shot_type,
+                None => log::warn!("Unknown shot type {}, use {}", name, self.shot_type.name),
+            }
+        }
+        log::info!("Gaming state starting with seed {}", self.seed);
+        self.random = Random::new(self.seed);
+        let mut game = self.script_game_data();
+        self.player.pos.x = PLAYER_SPAWN.0;
+        self.player.pos.y = PLAYER_SPAWN.1;
         self.player.tex = data.global_state.handles.texture_map.read().unwrap()["sheep"];
         data.render.render2d.add_tex(data.global_state, self.player.tex);
+        self.player_bullet_tex = data.global_state.handles.texture_map.read().unwrap()["sheepBullet"];
+        data.render.render2d.add_tex(data.global_state, self.player_bullet_tex);
+        self.bomb_tex = data.global_state.handles.texture_map.read().unwrap()["circle_blue"];
+        data.render.render2d.add_tex(data.global_state, self.bomb_tex);
+        for (tex, name) in self.item_tex.iter_mut().zip(["circle_red", "circle_blue", "circle_green", "circle_purple", "circle_yellow"]) {
+            *tex = data.global_state.handles.texture_map.read().unwrap()[name];
+            data.render.render2d.add_tex(data.global_state, *tex);
+        }
         self.script_manager.load_scripts();
+        self.script_watcher = ScriptWatcher::new(script_dir());
         log::info!("loaded all scripts");
         {
             let script = self.script_manager.get_script("main").unwrap();
             let mut context = ScriptContext::new(&script, vec![]);
 
 
-            let mut temp = TempGameContext {
-                tran: None,
-            };
-            context.execute_function("start", &mut game, &mut self.script_manager, &mut temp);
+            let mut temp = TempGameContext::default();
+            context.execute_function("start", &mut game.host(&mut temp), &self.script_manager);
         }
-        for x in game.submit_command {
+        for x in std::mem::take(&mut game.submit_command) {
             match x {
                 crate::script::ScriptGameCommand::SummonEnemy(name, x, y, z, hp, collide, script_name, args) => {
-                    let script = self.script_manager.get_script(&script_name).expect(&format!("Using unloaded script {}", name));
+                    let script = match self.script_manager.get_script(&script_name) {
+                        Some(script) => script,
+                        None => {
+                            log::error!("Summon enemy with unknown script {}", script_name);
+                            continue;
+                        }
+                    };
                     let lock = data.global_state.handles.texture_map.read().unwrap();
                     let tex = if let Some(x) = lock.get(&name) {
                         let x = *x;
@@ -134,18 +605,16 @@ impl GameState for Gaming {
                     };
                     data.render.render2d.add_tex(data.global_state, tex);
                     let id = self.next_obj_id();
-                    self.enemies.push(Enemy {
-                        pos: (x, y, z).into(),
-                        tex,
-                        collide,
-                        script: ScriptContext::new(script, args),
-                        hp,
-                        id,
-                    });
+                    let mut enemy = Enemy::new((x, y, z).into(), hp, collide, ScriptContext::new(script, args), tex, id);
+                    //the summons of on_spawn are handled in the first tick
+                    enemy.callback(ON_SPAWN_FUNCTION, &[], &mut game, &self.script_manager, &self.commands.0);
+                    self.enemies.push(enemy);
                 }
+                ScriptGameCommand::Kill => log::warn!("The main script is killed in start"),
                 _ => panic!("没实现哪里来的命令（大声）")
             }
         }
+        self.random = std::mem::take(&mut game.random);
 
         log::info!("Gaming state started.");
     }
@@ -154,6 +623,10 @@ impl GameState for Gaming {
         if data.inputs.is_pressed(&[VirtualKeyCode::F5]) {
             self.pausing = !self.pausing;
         }
+        if data.inputs.is_pressed(&[VirtualKeyCode::F9]) {
+            self.reload_scripts();
+        }
+        self.debug_script(data);
         (Trans::None, LoopState::POLL)
     }
 
@@ -169,90 +642,97 @@ impl GameState for Gaming {
 
         let input = &data.inputs.cur_game_input;
         self.player.walking = input.slow > 0;
-        let (mov_x, mov_y) = input.get_move(if self.player.walking {
-            self.player.walk_speed
-        } else {
-            self.player.move_speed
-        });
-        self.player.pos.x += mov_x;
-        self.player.pos.y += mov_y;
-        self.player.pos.x = self.player.pos.x.min(pthapi::GAME_MAX_X).max(pthapi::GAME_MIN_X);
-        self.player.pos.y = self.player.pos.y.min(pthapi::GAME_MAX_Y).max(pthapi::GAME_MIN_Y);
+        if self.player.death == 0 {
+            let (mov_x, mov_y) = input.get_move(if self.player.walking {
+                self.player.walk_speed
+            } else {
+                self.player.move_speed
+            });
+            self.player.pos.x += mov_x;
+            self.player.pos.y += mov_y;
+            self.player.pos.x = self.player.pos.x.min(pthapi::GAME_MAX_X).max(pthapi::GAME_MIN_X);
+            self.player.pos.y = self.player.pos.y.min(pthapi::GAME_MAX_Y).max(pthapi::GAME_MIN_Y);
+        }
+        if self.player.shoot_cooldown > 0 {
+            self.player.shoot_cooldown -= 1;
+        } else if input.shoot > 0 && self.player.death == 0 {
+            self.shoot();
+        }
 
-        let mut game_data = ScriptGameData {
-            player_tran: self.player.pos,
-            submit_command: Default::default(),
-            calc_stack: Default::default(),
-        };
+        let mut game_data = self.script_game_data();
 
         let mut idx = 0;
-        'bl:
-        loop {
-            'bullet_for:
-            for bullet in &mut self.player_bullets[idx..] {
-                {
-                    for (idx, enemy) in self.enemies.iter_mut().enumerate() {
-                        if enemy.hp <= 0.0 {
-                            continue;
-                        }
-                        if enemy.collide.is_collide_with_point(&enemy.pos, &bullet.pos) {
-                            enemy.hp -= bullet.damage;
-                            if enemy.hp <= 0.0 {
-                                let mut temp = TempGameContext {
-                                    tran: Some(&mut enemy.pos),
-                                };
-                                let result = enemy.script.exe_fn_if_present(&ON_DIE_FUNCTION.to_string(), &mut game_data, &mut self.script_manager, &mut temp)
-                                    .unwrap_or(0.0);
-                                self.enemies.swap_remove(idx);
-                                if result == 9.0 {
-                                    //anime here
-                                }
-                            }
-                            continue 'bullet_for;
-                        }
-                    }
+        while idx < self.player_bullets.len() {
+            let bullet = &mut self.player_bullets[idx];
+            if bullet.kind == ShotKind::Homing {
+                let nearest = self.enemies.iter().filter(|x| x.hp > 0.0).min_by(|a, b| {
+                    distance_2(&a.pos, &bullet.pos).total_cmp(&distance_2(&b.pos, &bullet.pos))
+                });
+                if let Some(enemy) = nearest {
+                    bullet.rot.add_angle(shot::homing_turn(&bullet.rot, &bullet.pos, &enemy.pos));
+                }
+            }
+            bullet.pos.x += bullet.speed * bullet.rot.facing_x;
+            bullet.pos.y += bullet.speed * bullet.rot.facing_y;
+            let (pos, damage, kind) = (bullet.pos, bullet.damage, bullet.kind);
+            let mut hit = false;
+            let mut enemy_idx = 0;
+            while enemy_idx < self.enemies.len() {
+                let enemy = &self.enemies[enemy_idx];
+                if enemy.hp <= 0.0 || !enemy.collide.is_collide_with_point(&enemy.pos, &pos) {
+                    enemy_idx += 1;
+                    continue;
+                }
+                hit = true;
+                let removed = self.damage_enemy(enemy_idx, damage, &mut game_data);
+                if kind != ShotKind::Piercing {
+                    break;
                 }
-                bullet.pos.y += 30.0;
-                if is_out_of_game(&bullet.pos) {
-                    self.player_bullets.swap_remove(idx);
-                    continue 'bl;
+                if !removed {
+                    enemy_idx += 1;
                 }
+            }
+            if (hit && kind != ShotKind::Piercing) || is_out_of_game(&pos) {
+                self.player_bullets.swap_remove(idx);
+            } else {
                 idx += 1;
             }
-            break;
         }
 
 
 
         use rayon::iter::ParallelIterator;
-        let script_manager = &mut self.script_manager;
-        self.enemy_bullets.par_iter_mut().for_each_with((self.commands.0.clone(), ScriptGameData::default()), |(sender, ref mut data), enemy_bullet| {
+        let script_manager = &self.script_manager;
+        self.enemy_bullets.par_iter_mut().for_each_with((self.commands.0.clone(), game_data.snapshot()), |(sender, ref mut data), enemy_bullet| {
             let bullet_tran = &mut enemy_bullet.pos;
             if is_out_of_game(bullet_tran) {
-                enemy_bullet.died = true;
+                //on_leave_screen is called in the serial pass
                 return;
             }
 
+            enemy_bullet.age += 1;
+            enemy_bullet.speed = std::mem::take(&mut enemy_bullet.moved);
             let mut temp = TempGameContext {
-                tran: Some(bullet_tran)
+                tran: Some(bullet_tran),
+                angle: enemy_bullet.rot.angle,
+                speed: enemy_bullet.speed,
+                age: enemy_bullet.age,
+                ..Default::default()
             };
 
-            enemy_bullet.script.tick_function(data, script_manager, &mut temp, true);
+            enemy_bullet.script.tick_function(&mut data.host(&mut temp), script_manager, Some(true));
             while let Some(x) = data.submit_command.pop_front() {
                 match x {
                     crate::script::ScriptGameCommand::Move(v) => {
-                        bullet_tran.x += enemy_bullet.rot.facing_x * v;
-                        bullet_tran.y += enemy_bullet.rot.facing_y * v;
+                        enemy_bullet.move_up(v);
                     }
                     crate::script::ScriptGameCommand::Kill => {
                         enemy_bullet.died = true;
                     }
-                    crate::script::ScriptGameCommand::SummonBullet(..) => {
+                    crate::script::ScriptGameCommand::SummonBullet(..) | crate::script::ScriptGameCommand::SummonEnemy(..)
+                    | crate::script::ScriptGameCommand::DropItem(..) => {
                         sender.send(x).unwrap();
                     }
-                    _ => {
-                        unimplemented!("Not ready");
-                    }
                 }
             }
         });
@@ -264,37 +744,49 @@ impl GameState for Gaming {
             }
             //SAFETY: we checked the len before
             for enemy_bullet in unsafe { self.enemy_bullets.get_unchecked_mut(idx..) } {
-                let bullet_tran = &mut enemy_bullet.pos;
-                if enemy_bullet.died || is_out_of_game(bullet_tran) {
+                if is_out_of_game(&enemy_bullet.pos) {
+                    enemy_bullet.callback(ON_LEAVE_SCREEN_FUNCTION, &[], &mut game_data, &self.script_manager, &self.commands.0);
                     self.enemy_bullets.swap_remove(idx);
                     continue 'el;
                 }
+                if enemy_bullet.died {
+                    self.enemy_bullets.swap_remove(idx);
+                    continue 'el;
+                }
+                let bullet_tran = &mut enemy_bullet.pos;
 
                 let mut temp = TempGameContext {
-                    tran: Some(bullet_tran)
+                    tran: Some(bullet_tran),
+                    angle: enemy_bullet.rot.angle,
+                    speed: enemy_bullet.speed,
+                    age: enemy_bullet.age,
+                    ..Default::default()
                 };
-                enemy_bullet.script.tick_function(&mut game_data, &mut self.script_manager, &mut temp, false);
+                enemy_bullet.script.tick_function(&mut game_data.host(&mut temp), &self.script_manager, Some(false));
                 let mut killed = false;
                 while let Some(x) = game_data.submit_command.pop_front() {
                     match x {
                         crate::script::ScriptGameCommand::Move(v) => {
-                            bullet_tran.x += enemy_bullet.rot.facing_x * v;
-                            bullet_tran.y += enemy_bullet.rot.facing_y * v;
+                            enemy_bullet.move_up(v);
                         }
                         crate::script::ScriptGameCommand::Kill => {
                             if !killed {
                                 killed = true;
                             }
                         }
-                        crate::script::ScriptGameCommand::SummonBullet(..) => {
+                        crate::script::ScriptGameCommand::SummonBullet(..) | crate::script::ScriptGameCommand::SummonEnemy(..)
+                        | crate::script::ScriptGameCommand::DropItem(..) => {
                             self.commands.0.send(x).unwrap();
                         }
-                        _ => {
-                            unimplemented!("Not ready")
-                        }
                     }
                 }
-                if killed {
+                enemy_bullet.died |= killed;
+                if graze::graze(&mut self.player, &mut enemy_bullet.grazed, enemy_bullet.collide, &enemy_bullet.pos) {
+                    game_data.player_graze = self.player.graze;
+                    enemy_bullet.callback(ON_GRAZE_FUNCTION, &[], &mut game_data, &self.script_manager, &self.commands.0);
+                }
+                enemy_bullet.check_timeout(&mut game_data, &self.script_manager, &self.commands.0);
+                if enemy_bullet.died {
                     self.enemy_bullets.swap_remove(idx);
                     continue 'el;
                 }
@@ -302,40 +794,67 @@ impl GameState for Gaming {
             }
             break;
         }
+        for bullet in &mut self.simple_bullets {
+            graze::graze(&mut self.player, &mut bullet.grazed, bullet.collide, &bullet.pos);
+        }
+        game_data.player_graze = self.player.graze;
 
         for enemy in &mut self.enemies {
+            enemy.age += 1;
             let enemy_tran = &mut enemy.pos;
             let mut temp = TempGameContext {
-                tran: Some(enemy_tran)
+                tran: Some(enemy_tran),
+                hp: enemy.hp,
+                age: enemy.age,
+                ..Default::default()
             };
-            enemy.script.tick_function(&mut game_data, &mut self.script_manager, &mut temp, true);
+            enemy.script.tick_function(&mut game_data.host(&mut temp), &self.script_manager, None);
 
             while let Some(x) = game_data.submit_command.pop_front() {
                 match x {
                     ScriptGameCommand::SummonBullet(..) => {
                         self.commands.0.send(x).unwrap();
                     }
-                    ScriptGameCommand::SummonEnemy(..) => {
+                    ScriptGameCommand::SummonEnemy(..) | ScriptGameCommand::DropItem(..) => {
                         self.commands.0.send(x).unwrap();
                     }
-                    _ => {
-                        unimplemented!("Not ready")
+                    ScriptGameCommand::Kill => {
+                        enemy.died = true;
                     }
+                    //the enemy has no facing, it moves by setting its position
+                    ScriptGameCommand::Move(_) => {}
                 }
             }
+            let on_screen = !is_out_of_game(&enemy.pos);
+            if enemy.on_screen && !on_screen {
+                enemy.callback(ON_LEAVE_SCREEN_FUNCTION, &[], &mut game_data, &self.script_manager, &self.commands.0);
+            }
+            enemy.on_screen = on_screen;
+            enemy.check_timeout(&mut game_data, &self.script_manager, &self.commands.0);
         }
+        self.enemies.retain(|x| !x.died);
+        self.tick_bomb(&mut game_data);
 
         while let Ok(x) = self.commands.1.try_recv() {
             match x {
                 ScriptGameCommand::SummonBullet(name, x, y, z, scale, angle, collide, script, args) => {
+                    //the names could be got at runtime so they may not exist
                     let script_context;
                     if let Some(script) = self.script_manager.get_script(&script) {
                         script_context = ScriptContext::new(script, args);
-                    } else {
-                        let script = self.script_manager.load_script(&script).unwrap();
+                    } else if let Some(script) = self.script_manager.load_script(&script) {
                         script_context = ScriptContext::new(script, args);
+                    } else {
+                        log::error!("Summon bullet with unknown script {}", script);
+                        continue;
                     }
-                    let tex = data.global_state.handles.texture_map.read().unwrap()[&name];
+                    let tex = match data.global_state.handles.texture_map.read().unwrap().get(&name) {
+                        Some(tex) => *tex,
+                        None => {
+                            log::error!("Summon bullet with unknown texture {}", name);
+                            continue;
+                        }
+                    };
                     data.render.render2d.add_tex(data.global_state, tex);
 
                     self.enemy_bullets.push(EnemyBullet {
@@ -347,34 +866,68 @@ impl GameState for Gaming {
                         script: script_context,
                         died: false,
                         id: self.next_obj_id(),
+                        speed: 0.0,
+                        moved: 0.0,
+                        age: 0,
+                        grazed: false,
                     });
+                    let bullet = self.enemy_bullets.last_mut().unwrap();
+                    bullet.callback(ON_SPAWN_FUNCTION, &[], &mut game_data, &self.script_manager, &self.commands.0);
                 }
                 ScriptGameCommand::SummonEnemy(name, x, y, z, hp, collide, script, args) => {
                     let script_context;
                     if let Some(script) = self.script_manager.get_script(&script) {
                         script_context = ScriptContext::new(script, args);
-                    } else {
-                        let script = self.script_manager.load_script(&script).unwrap();
+                    } else if let Some(script) = self.script_manager.load_script(&script) {
                         script_context = ScriptContext::new(script, args);
+                    } else {
+                        log::error!("Summon enemy with unknown script {}", script);
+                        continue;
                     }
+                    let tex = match data.global_state.handles.texture_map.read().unwrap().get(&name) {
+                        Some(tex) => *tex,
+                        None => {
+                            log::error!("Summon enemy with unknown texture {}", name);
+                            continue;
+                        }
+                    };
+                    data.render.render2d.add_tex(data.global_state, tex);
+
+                    let mut enemy = Enemy::new((x, y, z).into(), hp, collide, script_context, tex, self.next_obj_id());
+                    enemy.callback(ON_SPAWN_FUNCTION, &[], &mut game_data, &self.script_manager, &self.commands.0);
+                    self.enemies.push(enemy);
                 }
-                _ => {
-                    unimplemented!("Not ready")
+                ScriptGameCommand::DropItem(kind, x, y, count) => {
+                    //scattered around the object
+                    for _ in 0..count {
+                        let pos = (x + game_data.random.range(-30.0, 30.0), y + game_data.random.range(-30.0, 30.0), 0.0).into();
+                        self.items.push(item::drop_item(kind, pos));
+                    }
+                }
+                //only the summons and the items are sent
+                command @ (ScriptGameCommand::Move(_) | ScriptGameCommand::Kill) => {
+                    log::warn!("Unexpected command {:?} sent to the game", command);
                 }
             }
         }
 
-        if game_data.calc_stack.last_idx != -1 {
-            log::warn!("Not balance");
-        }
-        Trans::None
+        self.random = game_data.random;
+        item::tick_items(&mut self.items, &mut self.player);
+        self.tick_player(data.inputs.cur_game_input.bomb == 1)
     }
 
     fn render(&mut self, data: &mut StateData) -> Trans {
         profiling::scope!("Game render task");
         self.obj.clear();
 
-        self.obj.push(Texture2DObject::with_game_pos(self.player.pos, 100.0, 100.0, self.player.tex, 0));
+        //the player blinks while it could not be hit
+        if self.player.death >= 0 && self.invulnerable / 4 % 2 == 0 {
+            self.obj.push(Texture2DObject::with_game_pos(self.player.pos, 100.0, 100.0, self.player.tex, 0));
+        }
+        if let Some(bomb) = &self.bomb {
+            self.obj.push(Texture2DObject::with_game_pos(bomb.pos, bomb.radius * 2.0, bomb.radius * 2.0, self.bomb_tex, 2));
+        }
+        self.obj.extend(self.items.iter().map(|x| Texture2DObject::with_game_pos(x.pos, 24.0, 24.0, self.item_tex[x.kind as usize], 3)));
         use rayon::iter::ParallelIterator;
         self.obj.par_extend(self.player_bullets.par_iter().map(|x| Texture2DObject::with_game_pos(x.pos, 20.0, 20.0, x.tex, 1)));
         self.obj.par_extend(self.enemy_bullets.par_iter().map(|x| Texture2DObject::with_game_pos(x.pos, 100.0 * x.scale, 100.0 * x.scale, x.tex, x.id)));
@@ -567,3 +1120,7 @@ fn boss_die_anime<'a>(entities: &Entities<'a>,
 pub fn is_out_of_game(tran: &GamePos) -> bool {
     tran.x < GAME_MIN_X - 100.0 || tran.x > GAME_MAX_X + 100.0 || tran.y > GAME_MAX_Y + 100.0 || tran.y < GAME_MIN_Y - 100.0
 }
+
+fn distance_2(a: &GamePos, b: &GamePos) -> f32 {
+    (a.x - b.x).powi(2) + (a.y - b.y).powi(2)
+}
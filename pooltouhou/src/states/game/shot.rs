@@ -0,0 +1,144 @@
+use pthapi::{GamePos, Rotation, ShotKind};
+
+/// The most degrees a homing bullet turns in a tick
+pub const HOMING_TURN: f32 = 8.0;
+
+/// One stream of the player bullets
+pub struct Shot {
+    /// the offset from the player
+    pub x: f32,
+    pub y: f32,
+    /// the game angle, up is 90
+    pub angle: f32,
+    pub speed: f32,
+    pub damage: f32,
+    pub kind: ShotKind,
+    /// the power the player needs to shoot the stream
+    pub power: f32,
+}
+
+/// The streams shot together, the focused ones are shot while walking
+pub struct ShotType {
+    pub name: &'static str,
+    /// the ticks between two shots
+    pub cooldown: u8,
+    pub unfocused: &'static [Shot],
+    pub focused: &'static [Shot],
+}
+
+impl ShotType {
+    /// The streams could be shot with the power
+    pub fn shots(&self, focused: bool, power: f32) -> impl Iterator<Item=&Shot> {
+        let shots = if focused { self.focused } else { self.unfocused };
+        shots.iter().filter(move |x| x.power <= power)
+    }
+}
+
+const fn shot(x: f32, angle: f32, damage: f32, kind: ShotKind, power: f32) -> Shot {
+    Shot { x, y: 20.0, angle, speed: 30.0, damage, kind, power }
+}
+
+/// The first one is the default, pick one by `shot_type` in the config
+pub const SHOT_TYPES: &[ShotType] = &[
+    ShotType {
+        name: "spread",
+        cooldown: 4,
+        unfocused: &[
+            shot(0.0, 90.0, 10.0, ShotKind::Straight, 0.0),
+            shot(-10.0, 95.0, 8.0, ShotKind::Straight, 2.0),
+            shot(10.0, 85.0, 8.0, ShotKind::Straight, 2.0),
+            shot(-20.0, 100.0, 8.0, ShotKind::Straight, 3.0),
+            shot(20.0, 80.0, 8.0, ShotKind::Straight, 3.0),
+            shot(-30.0, 105.0, 8.0, ShotKind::Straight, 4.0),
+            shot(30.0, 75.0, 8.0, ShotKind::Straight, 4.0),
+        ],
+        focused: &[
+            shot(0.0, 90.0, 10.0, ShotKind::Straight, 0.0),
+            shot(-10.0, 90.0, 8.0, ShotKind::Straight, 2.0),
+            shot(10.0, 90.0, 8.0, ShotKind::Straight, 2.0),
+            shot(-20.0, 91.0, 8.0, ShotKind::Straight, 3.0),
+            shot(20.0, 89.0, 8.0, ShotKind::Straight, 3.0),
+            shot(-30.0, 92.0, 8.0, ShotKind::Straight, 4.0),
+            shot(30.0, 88.0, 8.0, ShotKind::Straight, 4.0),
+        ],
+    },
+    ShotType {
+        name: "homing",
+        cooldown: 5,
+        unfocused: &[
+            shot(-15.0, 120.0, 8.0, ShotKind::Homing, 0.0),
+            shot(15.0, 60.0, 8.0, ShotKind::Homing, 0.0),
+            shot(-30.0, 150.0, 8.0, ShotKind::Homing, 2.0),
+            shot(30.0, 30.0, 8.0, ShotKind::Homing, 2.0),
+            shot(0.0, 90.0, 8.0, ShotKind::Homing, 3.0),
+            shot(-45.0, 180.0, 8.0, ShotKind::Homing, 4.0),
+            shot(45.0, 0.0, 8.0, ShotKind::Homing, 4.0),
+        ],
+        focused: &[
+            shot(0.0, 90.0, 12.0, ShotKind::Straight, 0.0),
+            shot(-15.0, 100.0, 8.0, ShotKind::Homing, 2.0),
+            shot(15.0, 80.0, 8.0, ShotKind::Homing, 2.0),
+            shot(-30.0, 110.0, 8.0, ShotKind::Homing, 3.0),
+            shot(30.0, 70.0, 8.0, ShotKind::Homing, 3.0),
+            shot(-10.0, 90.0, 10.0, ShotKind::Straight, 4.0),
+            shot(10.0, 90.0, 10.0, ShotKind::Straight, 4.0),
+        ],
+    },
+    ShotType {
+        name: "laser",
+        //the piercing bullets hit every tick they are in the enemy, so they are weak
+        cooldown: 2,
+        unfocused: &[
+            shot(0.0, 90.0, 2.0, ShotKind::Piercing, 0.0),
+            shot(-20.0, 93.0, 2.0, ShotKind::Piercing, 2.0),
+            shot(20.0, 87.0, 2.0, ShotKind::Piercing, 2.0),
+            shot(-40.0, 96.0, 2.0, ShotKind::Piercing, 4.0),
+            shot(40.0, 84.0, 2.0, ShotKind::Piercing, 4.0),
+        ],
+        focused: &[
+            shot(0.0, 90.0, 3.0, ShotKind::Piercing, 0.0),
+            shot(-8.0, 90.0, 2.0, ShotKind::Piercing, 2.0),
+            shot(8.0, 90.0, 2.0, ShotKind::Piercing, 2.0),
+            shot(-16.0, 90.0, 2.0, ShotKind::Piercing, 4.0),
+            shot(16.0, 90.0, 2.0, ShotKind::Piercing, 4.0),
+        ],
+    },
+];
+
+pub fn shot_type(name: &str) -> Option<&'static ShotType> {
+    SHOT_TYPES.iter().find(|x| x.name == name)
+}
+
+/// The degrees a homing bullet turns to the target in a tick
+pub fn homing_turn(rot: &Rotation, pos: &GamePos, target: &GamePos) -> f32 {
+    let angle = (target.y - pos.y).atan2(target.x - pos.x).to_degrees();
+    let diff = (angle - rot.angle).rem_euclid(360.0);
+    let diff = if diff > 180.0 { diff - 360.0 } else { diff };
+    diff.clamp(-HOMING_TURN, HOMING_TURN)
+}
+
+#[cfg(test)]
+mod test {
+    use pthapi::{GamePos, Rotation};
+
+    use crate::states::game::shot::{HOMING_TURN, homing_turn, SHOT_TYPES, shot_type};
+
+    #[test]
+    fn more_shots_with_power() {
+        let spread = shot_type("spread").unwrap();
+        assert_eq!(1, spread.shots(false, 1.0).count());
+        assert_eq!(7, spread.shots(false, 4.0).count());
+        assert!(SHOT_TYPES.iter().all(|x| x.shots(true, 1.0).count() > 0 && x.shots(false, 1.0).count() > 0));
+    }
+
+    #[test]
+    fn turn_to_target() {
+        let pos = GamePos::default();
+        let up = Rotation::new(90.0);
+        assert_eq!(0.0, homing_turn(&up, &pos, &(0.0, 100.0, 0.0).into()));
+        assert_eq!(-HOMING_TURN, homing_turn(&up, &pos, &(100.0, 0.0, 0.0).into()));
+        assert_eq!(HOMING_TURN, homing_turn(&up, &pos, &(-100.0, 1.0, 0.0).into()));
+        //the angle of the rotation is not wrapped
+        assert!((homing_turn(&Rotation::new(450.0), &pos, &(1.0, 100.0, 0.0).into()) + 0.57).abs() < 0.01);
+    }
+}
@@ -32,6 +32,14 @@ pub struct Player {
     pub tex: usize,
     pub shoot_cooldown: u8,
     pub walking: bool,
+    pub lives: u8,
+    pub bombs: u8,
+    pub power: f32,
+    /// the bullets in it are grazed
+    pub graze_radius: f32,
+    /// the bullets grazed
+    pub graze: u32,
+    pub score: u64,
 }
 
 #[repr(C)]
@@ -161,16 +169,36 @@ impl Player {
             shoot_cooldown: 0,
             death: 0,
             tex: 0,
+            lives: 3,
+            bombs: 3,
+            power: 1.0,
+            graze_radius: 30.0,
+            graze: 0,
+            score: 0,
         }
     }
 }
 
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ShotKind {
+    #[default]
+    Straight,
+    /// turns to the nearest enemy
+    Homing,
+    /// is not removed when it hits, so it hits every tick in the enemy
+    Piercing,
+}
+
 #[repr(C)]
 #[derive(Default)]
 pub struct PlayerBullet {
     pub pos: GamePos,
     pub tex: TexHandle,
     pub damage: f32,
+    pub speed: f32,
+    pub rot: Rotation,
+    pub kind: ShotKind,
 }
 
 
@@ -213,6 +241,8 @@ pub struct SimpleEnemyBullet {
     pub a_delta: f32,
     pub w: f32,
     pub w_delta: f32,
+    /// it is counted in the graze of the player
+    pub grazed: bool,
 }
 
 impl SimpleEnemyBullet {
@@ -233,6 +263,7 @@ impl SimpleEnemyBullet {
             a_delta: 0.0,
             w: 0.0,
             w_delta: 0.0,
+            grazed: false,
         }
     }
 